@@ -1,31 +1,1044 @@
 use std::{
-    net::UdpSocket,
+    fmt, io,
+    net::{AddrParseError, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
     sync::{Arc, Mutex},
     thread,
+    time::{Duration, Instant},
 };
 
-use artnet_protocol::{ArtCommand, Output};
+use artnet_protocol::{ArtCommand, Output, Poll, PollReply, PortAddress};
+use serde::{Deserialize, Serialize};
 
-use lib::universe::Universe;
+use lib::{
+    input::{InputSource, MergeMode},
+    show::Show,
+};
 
-use crate::lib;
+use crate::{
+    lib,
+    output::{DmxOutput, OutputHealth, frame_due},
+};
 
-pub fn launch_artnet_send_thread(universe: Arc<Mutex<Universe>>) {
-    thread::spawn(move || {
-        let socket = UdpSocket::bind(("0.0.0.0", 0)).unwrap();
-        println!("Created Socket for ArtNet");
+pub const ARTNET_PORT: u16 = 6454;
+
+/// A 15 bit Art-Net port address, either as a plain number or split into
+/// net (0-127), sub-net (0-15) and universe (0-15).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PortAddressConfig {
+    Number(u16),
+    Parts { net: u8, subnet: u8, universe: u8 },
+}
+
+impl PortAddressConfig {
+    pub fn to_port_address(&self) -> Result<PortAddress, String> {
+        match *self {
+            PortAddressConfig::Number(number) => PortAddress::try_from(number)
+                .map_err(|_| format!("port address {} is not within 0-32767", number)),
+            PortAddressConfig::Parts {
+                net,
+                subnet,
+                universe,
+            } => {
+                if net > 127 {
+                    return Err(format!("net {} is not within 0-127", net));
+                }
+                if subnet > 15 {
+                    return Err(format!("subnet {} is not within 0-15", subnet));
+                }
+                if universe > 15 {
+                    return Err(format!("universe {} is not within 0-15", universe));
+                }
+                let number = (net as u16) << 8 | (subnet as u16) << 4 | universe as u16;
+                Ok(PortAddress::try_from(number).expect("15 bit port address"))
+            }
+        }
+    }
+}
+
+/// Which Art-Net port address a logical universe is sent to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UniverseMapping {
+    pub universe: u16,
+    pub port_address: PortAddressConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtNetOutputConfig {
+    /// Unicast address of a node, a directed broadcast (e.g. `2.255.255.255`)
+    /// or the limited broadcast `255.255.255.255`.
+    #[serde(default = "default_destination")]
+    pub destination: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Local address of the interface to send from, `0.0.0.0` lets the OS pick.
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// Frames per second, at most the 44 the render loop runs at.
+    #[serde(default = "default_frame_rate")]
+    pub frame_rate: f64,
+    /// An unchanged universe is resent after this many milliseconds.
+    #[serde(default = "default_keep_alive_ms")]
+    pub keep_alive_ms: u64,
+    /// Send ArtSync after each batch of ArtDmx, so nodes in synchronous
+    /// mode update all universes at once.
+    #[serde(default)]
+    pub sync: bool,
+    /// Universes sent by this output. When empty every universe of the show
+    /// is sent to the port address with the same number.
+    #[serde(default)]
+    pub universes: Vec<UniverseMapping>,
+}
+
+fn default_destination() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    ARTNET_PORT
+}
+
+fn default_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_frame_rate() -> f64 {
+    44.0
+}
+
+fn default_keep_alive_ms() -> u64 {
+    2000
+}
+
+impl Default for ArtNetOutputConfig {
+    fn default() -> Self {
+        ArtNetOutputConfig {
+            destination: default_destination(),
+            port: default_port(),
+            bind: default_bind(),
+            frame_rate: default_frame_rate(),
+            keep_alive_ms: default_keep_alive_ms(),
+            sync: false,
+            universes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArtNetError {
+    InvalidDestination(String, AddrParseError),
+    InvalidBind(String, AddrParseError),
+    InvalidPortAddress { universe: u16, reason: String },
+    InvalidFrameRate(f64),
+    Bind(String, io::Error),
+    Broadcast(io::Error),
+}
+
+impl fmt::Display for ArtNetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtNetError::InvalidDestination(address, e) => {
+                write!(f, "invalid Art-Net destination '{}': {}", address, e)
+            }
+            ArtNetError::InvalidBind(address, e) => {
+                write!(f, "invalid Art-Net bind address '{}': {}", address, e)
+            }
+            ArtNetError::InvalidPortAddress { universe, reason } => {
+                write!(
+                    f,
+                    "invalid Art-Net port address for universe {}: {}",
+                    universe, reason
+                )
+            }
+            ArtNetError::InvalidFrameRate(rate) => {
+                write!(f, "invalid Art-Net frame rate {}, must be above 0", rate)
+            }
+            ArtNetError::Bind(address, e) => {
+                write!(f, "could not bind Art-Net socket to {}: {}", address, e)
+            }
+            ArtNetError::Broadcast(e) => write!(f, "could not enable broadcast: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ArtNetError {}
+
+impl ArtNetOutputConfig {
+    fn mappings(&self, show: &Show) -> Vec<UniverseMapping> {
+        if !self.universes.is_empty() {
+            return self.universes.clone();
+        }
+        show.universe_numbers()
+            .into_iter()
+            .map(|universe| UniverseMapping {
+                universe,
+                port_address: PortAddressConfig::Number(universe),
+            })
+            .collect()
+    }
+
+    /// Check the settings and open the socket. The output sends the show's
+    /// universes once it is added to an output manager.
+    pub fn open(&self, show: &Show) -> Result<ArtNetOutput, ArtNetError> {
+        let destination: Ipv4Addr = self
+            .destination
+            .parse()
+            .map_err(|e| ArtNetError::InvalidDestination(self.destination.clone(), e))?;
+        let bind: Ipv4Addr = self
+            .bind
+            .parse()
+            .map_err(|e| ArtNetError::InvalidBind(self.bind.clone(), e))?;
+        if !(self.frame_rate.is_finite() && self.frame_rate > 0.0) {
+            return Err(ArtNetError::InvalidFrameRate(self.frame_rate));
+        }
+
+        let mappings = self
+            .mappings(show)
+            .into_iter()
+            .map(|m| {
+                m.port_address
+                    .to_port_address()
+                    .map(|port_address| (m.universe, port_address))
+                    .map_err(|reason| ArtNetError::InvalidPortAddress {
+                        universe: m.universe,
+                        reason,
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let socket =
+            UdpSocket::bind((bind, 0)).map_err(|e| ArtNetError::Bind(self.bind.clone(), e))?;
+        // Needed for directed and limited broadcast, harmless for unicast
+        socket.set_broadcast(true).map_err(ArtNetError::Broadcast)?;
+        let local_port = socket
+            .local_addr()
+            .map_err(|e| ArtNetError::Bind(self.bind.clone(), e))?
+            .port();
+
+        let destination = SocketAddrV4::new(destination, self.port);
+        println!("Created Socket for ArtNet, sending to {}", destination);
+
+        Ok(ArtNetOutput {
+            socket,
+            local_port,
+            destination,
+            frame_interval: Duration::from_secs_f64(1.0 / self.frame_rate),
+            keep_alive: Duration::from_millis(self.keep_alive_ms),
+            sync: self.sync,
+            universes: mappings
+                .into_iter()
+                .map(|(universe, port_address)| UniverseState {
+                    universe,
+                    port_address,
+                    last_frame: None,
+                    last_sent: Instant::now(),
+                    sequence: 0,
+                })
+                .collect(),
+            sent: false,
+            health: OutputHealth::Stopped,
+        })
+    }
+}
+
+/// An Art-Net destination, see `ArtNetOutputConfig`.
+pub struct ArtNetOutput {
+    socket: UdpSocket,
+    local_port: u16,
+    destination: SocketAddrV4,
+    frame_interval: Duration,
+    keep_alive: Duration,
+    sync: bool,
+    universes: Vec<UniverseState>,
+    /// Whether ArtDmx went out since the last ArtSync.
+    sent: bool,
+    health: OutputHealth,
+}
+
+/// What was last sent for one logical universe.
+struct UniverseState {
+    universe: u16,
+    port_address: PortAddress,
+    last_frame: Option<[u8; 512]>,
+    last_sent: Instant,
+    /// 1-255, 0 would disable reordering on the node.
+    sequence: u8,
+}
+
+impl UniverseState {
+    /// See `output::frame_due`.
+    fn needs_send(
+        &self,
+        frame: &[u8; 512],
+        now: Instant,
+        frame_interval: Duration,
+        keep_alive: Duration,
+    ) -> bool {
+        frame_due(
+            self.last_frame.as_ref(),
+            frame,
+            now - self.last_sent,
+            frame_interval,
+            keep_alive,
+        )
+    }
+
+    fn next_sequence(&mut self) -> u8 {
+        self.sequence = if self.sequence == 255 {
+            1
+        } else {
+            self.sequence + 1
+        };
+        self.sequence
+    }
+}
+
+impl ArtNetOutput {
+    /// Local port the output sends from, so the receiver can skip our own
+    /// packets.
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    fn report(&mut self, result: io::Result<usize>, what: &str) {
+        match result {
+            Ok(_) => {
+                if matches!(self.health, OutputHealth::Degraded { .. }) {
+                    self.health = OutputHealth::Running;
+                }
+            }
+            Err(e) => {
+                let error = format!("failed to send {}: {}", what, e);
+                if self.health
+                    != (OutputHealth::Degraded {
+                        error: error.clone(),
+                    })
+                {
+                    eprintln!("Art-Net {}: {}", self.destination, error);
+                }
+                self.health = OutputHealth::Degraded { error };
+            }
+        }
+    }
+}
+
+impl DmxOutput for ArtNetOutput {
+    fn name(&self) -> String {
+        format!("Art-Net {}", self.destination)
+    }
+
+    fn universes(&self) -> Vec<u16> {
+        self.universes.iter().map(|state| state.universe).collect()
+    }
+
+    fn start(&mut self) {
+        self.health = OutputHealth::Running;
+    }
+
+    fn send_frame(&mut self, universe: u16, frame: &[u8; 512]) {
+        let now = Instant::now();
+        let mut results = Vec::new();
+        for state in self.universes.iter_mut().filter(|s| s.universe == universe) {
+            if !state.needs_send(frame, now, self.frame_interval, self.keep_alive) {
+                continue;
+            }
 
-        loop {
             let command = ArtCommand::Output(Output {
-                data: Vec::from(universe.lock().unwrap().get_dmx_values()).into(),
+                sequence: state.next_sequence(),
+                port_address: state.port_address,
+                data: Vec::from(*frame).into(),
                 ..Output::default()
             });
 
             let buffer = command.write_to_buffer().expect("Failed to serialize");
 
-            socket.send_to(&buffer, ("127.0.0.1", 6454)).unwrap();
-            //println!("Sent Art-Net DMX");
-            //info!("Sent Art-Net DMX");
+            results.push(self.socket.send_to(&buffer, self.destination));
+            state.last_frame = Some(*frame);
+            state.last_sent = now;
+            self.sent = true;
+        }
+        for result in results {
+            self.report(result, "ArtDmx");
+        }
+    }
+
+    fn end_of_frames(&mut self) {
+        if self.sync && self.sent {
+            let result = self.socket.send_to(&art_sync(), self.destination);
+            self.report(result, "ArtSync");
+        }
+        self.sent = false;
+    }
+
+    fn health(&self) -> OutputHealth {
+        self.health.clone()
+    }
+
+    fn stop(&mut self) {
+        self.health = OutputHealth::Stopped;
+    }
+}
+
+/// ArtSync, built by hand as artnet_protocol writes it without the
+/// protocol version and aux bytes.
+fn art_sync() -> [u8; 14] {
+    let mut packet = [0; 14];
+    packet[..8].copy_from_slice(b"Art-Net\0");
+    packet[8..10].copy_from_slice(&OP_SYNC.to_le_bytes());
+    // Protocol version 14, Aux1 and Aux2 are zero
+    packet[11] = 14;
+    packet
+}
+
+/// Settings for ArtPoll discovery and for answering the polls of other
+/// controllers. Both share one socket on port 6454 with the Art-Net input.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtNetDiscoveryConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// Where ArtPoll is sent, usually a broadcast address.
+    #[serde(default = "default_poll_destination")]
+    pub poll_destination: String,
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Names this controller answers ArtPoll with.
+    #[serde(default = "default_short_name")]
+    pub short_name: String,
+    #[serde(default = "default_long_name")]
+    pub long_name: String,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_poll_destination() -> String {
+    "255.255.255.255".to_string()
+}
+
+fn default_poll_interval_ms() -> u64 {
+    3000
+}
+
+fn default_short_name() -> String {
+    "illumix".to_string()
+}
+
+fn default_long_name() -> String {
+    "illumix lite lighting controller".to_string()
+}
+
+impl Default for ArtNetDiscoveryConfig {
+    fn default() -> Self {
+        ArtNetDiscoveryConfig {
+            enabled: default_enabled(),
+            bind: default_bind(),
+            poll_destination: default_poll_destination(),
+            poll_interval_ms: default_poll_interval_ms(),
+            short_name: default_short_name(),
+            long_name: default_long_name(),
+        }
+    }
+}
+
+/// A node that answered ArtPoll. Nodes with more than four ports answer
+/// once per group of ports, told apart by `bind_index`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtNetNode {
+    pub ip: Ipv4Addr,
+    pub bind_index: u8,
+    pub short_name: String,
+    pub long_name: String,
+    pub firmware: u16,
+    pub mac: String,
+    /// Port addresses the node outputs to DMX.
+    pub outputs: Vec<u16>,
+    /// Port addresses the node sends from its DMX inputs.
+    pub inputs: Vec<u16>,
+    #[serde(skip)]
+    last_seen: Instant,
+}
+
+impl ArtNetNode {
+    fn from_reply(reply: &PollReply, now: Instant) -> ArtNetNode {
+        let net = (reply.port_address[0] & 0x7f) as u16;
+        let subnet = (reply.port_address[1] & 0x0f) as u16;
+        let port_count = (reply.num_ports[1] as usize).min(4);
+
+        let mut outputs = Vec::new();
+        let mut inputs = Vec::new();
+        for port in 0..port_count {
+            if reply.port_types[port] & 0x80 != 0 {
+                outputs.push(net << 8 | subnet << 4 | (reply.swout[port] & 0x0f) as u16);
+            }
+            if reply.port_types[port] & 0x40 != 0 {
+                inputs.push(net << 8 | subnet << 4 | (reply.swin[port] & 0x0f) as u16);
+            }
+        }
+
+        ArtNetNode {
+            ip: reply.address,
+            bind_index: reply.bind_index,
+            short_name: c_string(&reply.short_name),
+            long_name: c_string(&reply.long_name),
+            firmware: u16::from_be_bytes(reply.version),
+            mac: reply
+                .mac
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+            outputs,
+            inputs,
+            last_seen: now,
+        }
+    }
+}
+
+/// Text up to the first NUL of a fixed size Art-Net string field.
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+/// Copy `text` into a fixed size Art-Net string field, leaving room for the NUL.
+fn fill_c_string<const N: usize>(text: &str) -> [u8; N] {
+    let mut field = [0; N];
+    for (dst, src) in field.iter_mut().zip(text.bytes().take(N - 1)) {
+        *dst = src;
+    }
+    field
+}
+
+/// Nodes discovered so far, sorted by IP.
+#[derive(Debug, Default)]
+pub struct NodeList {
+    nodes: Vec<ArtNetNode>,
+}
+
+impl NodeList {
+    pub fn nodes(&self) -> &[ArtNetNode] {
+        &self.nodes
+    }
+
+    fn update(&mut self, node: ArtNetNode) {
+        match self
+            .nodes
+            .iter_mut()
+            .find(|n| n.ip == node.ip && n.bind_index == node.bind_index)
+        {
+            Some(existing) => *existing = node,
+            None => {
+                println!("🔎 Found Art-Net node {} ({})", node.ip, node.short_name);
+                self.nodes.push(node);
+                self.nodes.sort_by_key(|n| (n.ip, n.bind_index));
+            }
+        }
+    }
+
+    /// Forget nodes that have not answered for `max_age`.
+    fn expire(&mut self, now: Instant, max_age: Duration) {
+        self.nodes.retain(|n| now - n.last_seen < max_age);
+    }
+}
+
+/// Receive ArtDmx from other consoles and merge it into the show. Uses the
+/// discovery socket, so `artnet_discovery.bind` selects the interface.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtNetInputConfig {
+    pub universes: Vec<ArtNetInputMapping>,
+}
+
+/// Which port address is merged into a logical universe, and how.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtNetInputMapping {
+    pub universe: u16,
+    pub port_address: PortAddressConfig,
+    #[serde(default)]
+    pub merge: MergeMode,
+}
+
+/// Art-Net merge timeout, a source that stops sending is dropped after this.
+const SOURCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Length of a current ArtPollReply. Older nodes send shorter replies, which
+/// are zero padded before parsing.
+const POLL_REPLY_LENGTH: usize = 239;
+const OP_POLL_REPLY: u16 = 0x2100;
+const OP_SYNC: u16 = 0x5200;
+
+/// Whether `ip` belongs to this host, so a packet from it may be our own.
+fn is_local(ip: std::net::IpAddr) -> bool {
+    ip.is_loopback() || UdpSocket::bind((ip, 0)).is_ok()
+}
+
+/// The address this host uses to reach `peer`, for the IP in our ArtPollReply.
+fn local_address_towards(peer: Ipv4Addr) -> io::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect((peer, ARTNET_PORT))?;
+    match socket.local_addr()?.ip() {
+        std::net::IpAddr::V4(ip) => Ok(ip),
+        std::net::IpAddr::V6(_) => Ok(Ipv4Addr::UNSPECIFIED),
+    }
+}
+
+impl ArtNetDiscoveryConfig {
+    fn poll_reply(&self, address: Ipv4Addr) -> PollReply {
+        PollReply {
+            address,
+            port: ARTNET_PORT,
+            version: [0, 1],
+            short_name: fill_c_string(&self.short_name),
+            long_name: fill_c_string(&self.long_name),
+            node_report: fill_c_string("#0001 [0000] illumix running"),
+            // StController
+            style: 0x01,
+            bind_ip: address.octets(),
+            ..PollReply::default()
+        }
+    }
+}
+
+/// Bind the Art-Net port and handle what arrives on it. With discovery
+/// enabled nodes are polled every `poll_interval_ms` and kept in `nodes`, and
+/// ArtPoll from other controllers is answered. With an `input` ArtDmx on the
+/// configured port addresses is merged into the show; packets sent from
+/// `own_ports` on this host are our own outputs and skipped.
+pub fn launch_artnet_receive_thread(
+    show: Arc<Mutex<Show>>,
+    config: ArtNetDiscoveryConfig,
+    input: Option<ArtNetInputConfig>,
+    nodes: Arc<Mutex<NodeList>>,
+    own_ports: Vec<u16>,
+) -> Result<(), ArtNetError> {
+    let poll_destination: Ipv4Addr = config
+        .poll_destination
+        .parse()
+        .map_err(|e| ArtNetError::InvalidDestination(config.poll_destination.clone(), e))?;
+    let bind: Ipv4Addr = config
+        .bind
+        .parse()
+        .map_err(|e| ArtNetError::InvalidBind(config.bind.clone(), e))?;
+    let inputs = input_mappings(input)?;
+
+    let socket = UdpSocket::bind((bind, ARTNET_PORT))
+        .map_err(|e| ArtNetError::Bind(format!("{}:{}", config.bind, ARTNET_PORT), e))?;
+    socket.set_broadcast(true).map_err(ArtNetError::Broadcast)?;
+    let poll_socket = socket.try_clone().map_err(ArtNetError::Broadcast)?;
+    println!("🔎 Art-Net listening on {}:{}", bind, ARTNET_PORT);
+
+    {
+        let mut show = show.lock().unwrap();
+        for (_, universe, merge) in &inputs {
+            show.inputs.set_mode(*universe, *merge);
+        }
+    }
+
+    let interval = Duration::from_millis(config.poll_interval_ms.max(100));
+    let poll_nodes = nodes.clone();
+    let discovery = config.enabled;
+    thread::spawn(move || {
+        if !discovery {
+            return;
+        }
+        let poll = ArtCommand::Poll(Poll::default())
+            .write_to_buffer()
+            .expect("Failed to serialize");
+        loop {
+            if let Err(e) = poll_socket.send_to(&poll, (poll_destination, ARTNET_PORT)) {
+                eprintln!("Failed to send ArtPoll to {}: {}", poll_destination, e);
+            }
+            thread::sleep(interval);
+            // A node that missed three polls is gone
+            poll_nodes
+                .lock()
+                .unwrap()
+                .expire(Instant::now(), interval * 3);
+        }
+    });
+
+    thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        loop {
+            let (length, source) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) => {
+                    eprintln!("Art-Net receive failed: {}", e);
+                    continue;
+                }
+            };
+            let mut length = length;
+            if length >= 10
+                && u16::from_le_bytes([buffer[8], buffer[9]]) == OP_POLL_REPLY
+                && length < POLL_REPLY_LENGTH
+            {
+                buffer[length..POLL_REPLY_LENGTH].fill(0);
+                length = POLL_REPLY_LENGTH;
+            }
+
+            match ArtCommand::from_buffer(&buffer[..length]) {
+                Ok(ArtCommand::Poll(_)) if discovery => {
+                    let std::net::SocketAddr::V4(source) = source else {
+                        continue;
+                    };
+                    let address = local_address_towards(*source.ip()).unwrap_or(bind);
+                    let reply = ArtCommand::PollReply(Box::new(config.poll_reply(address)))
+                        .write_to_buffer()
+                        .expect("Failed to serialize");
+                    if let Err(e) = socket.send_to(&reply, (*source.ip(), ARTNET_PORT)) {
+                        eprintln!("Failed to answer ArtPoll from {}: {}", source, e);
+                    }
+                }
+                Ok(ArtCommand::PollReply(reply)) if discovery => {
+                    nodes
+                        .lock()
+                        .unwrap()
+                        .update(ArtNetNode::from_reply(&reply, Instant::now()));
+                }
+                Ok(ArtCommand::Output(output)) => {
+                    if own_ports.contains(&source.port()) && is_local(source.ip()) {
+                        continue;
+                    }
+                    merge_art_dmx(&show, &inputs, source, &output);
+                }
+                Ok(_) | Err(_) => (),
+            }
         }
     });
+    Ok(())
+}
+
+/// (port address, universe, merge mode) of every input mapping.
+fn input_mappings(
+    input: Option<ArtNetInputConfig>,
+) -> Result<Vec<(u16, u16, MergeMode)>, ArtNetError> {
+    input
+        .map(|input| input.universes)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|m| {
+            m.port_address
+                .to_port_address()
+                .map(|port_address| (u16::from(port_address), m.universe, m.merge))
+                .map_err(|reason| ArtNetError::InvalidPortAddress {
+                    universe: m.universe,
+                    reason,
+                })
+        })
+        .collect()
+}
+
+/// Merge ArtDmx from `source` into the show if its port address is one of
+/// `inputs` (port address, universe, merge mode).
+fn merge_art_dmx(
+    show: &Mutex<Show>,
+    inputs: &[(u16, u16, MergeMode)],
+    source: SocketAddr,
+    output: &Output,
+) {
+    let port_address = u16::from(output.port_address);
+    let Some((_, universe, _)) = inputs.iter().find(|(p, _, _)| *p == port_address) else {
+        return;
+    };
+
+    let data = output.data.as_ref();
+    let mut frame = [0; 512];
+    let length = data.len().min(512);
+    frame[..length].copy_from_slice(&data[..length]);
+
+    // Art-Net has no priorities, sources merge with our own output on equal
+    // terms
+    let mut show = show.lock().unwrap();
+    let priority = show.inputs.local_priority;
+    show.inputs.update(
+        *universe,
+        InputSource::new(
+            format!("artnet:{}:{}", source, port_address),
+            format!("Art-Net {}", source.ip()),
+            priority,
+            frame,
+            SOURCE_TIMEOUT,
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OP_OUTPUT: u16 = 0x5000;
+
+    fn opcode(packet: &[u8]) -> u16 {
+        u16::from_le_bytes([packet[8], packet[9]])
+    }
+
+    /// Open a two universe output sending to a local listener.
+    fn open_output(sync: bool) -> (ArtNetOutput, UdpSocket) {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let config = ArtNetOutputConfig {
+            destination: "127.0.0.1".to_string(),
+            port: listener.local_addr().unwrap().port(),
+            sync,
+            universes: vec![
+                UniverseMapping {
+                    universe: 1,
+                    port_address: PortAddressConfig::Number(0),
+                },
+                UniverseMapping {
+                    universe: 2,
+                    port_address: PortAddressConfig::Number(1),
+                },
+            ],
+            ..ArtNetOutputConfig::default()
+        };
+        let mut output = config.open(&Show::new()).unwrap();
+        output.start();
+        (output, listener)
+    }
+
+    /// One pass of the output manager.
+    fn send(output: &mut ArtNetOutput, frames: [[u8; 512]; 2]) {
+        output.send_frame(1, &frames[0]);
+        output.send_frame(2, &frames[1]);
+        output.end_of_frames();
+    }
+
+    fn receive_opcodes(listener: &UdpSocket) -> Vec<u16> {
+        let mut buffer = [0u8; 1024];
+        let mut opcodes = Vec::new();
+        while let Ok(length) = listener.recv(&mut buffer) {
+            opcodes.push(opcode(&buffer[..length]));
+        }
+        opcodes
+    }
+
+    #[test]
+    fn sync_follows_each_batch() {
+        let (mut output, listener) = open_output(true);
+
+        send(&mut output, [[1; 512], [2; 512]]);
+        assert_eq!(
+            receive_opcodes(&listener),
+            vec![OP_OUTPUT, OP_OUTPUT, OP_SYNC]
+        );
+
+        // Only universe 2 changed
+        send(&mut output, [[1; 512], [3; 512]]);
+        assert_eq!(receive_opcodes(&listener), vec![OP_OUTPUT, OP_SYNC]);
+
+        // Nothing changed and no keep-alive due, no sync either
+        send(&mut output, [[1; 512], [3; 512]]);
+        assert_eq!(receive_opcodes(&listener), Vec::<u16>::new());
+    }
+
+    #[test]
+    fn changed_frames_go_out_every_render_tick() {
+        let (mut output, listener) = open_output(false);
+        let mut buffer = [0u8; 1024];
+
+        // A render loop at the output's own 44 Hz, each tick a little early
+        let tick = output.frame_interval.mul_f64(0.9);
+        for value in 1..=3u8 {
+            send(&mut output, [[value; 512], [value; 512]]);
+            for _ in 0..2 {
+                let length = listener.recv(&mut buffer).unwrap();
+                assert_eq!(opcode(&buffer[..length]), OP_OUTPUT);
+                assert_eq!(buffer[18], value);
+            }
+            std::thread::sleep(tick);
+        }
+    }
+
+    #[test]
+    fn no_sync_when_disabled() {
+        let (mut output, listener) = open_output(false);
+
+        send(&mut output, [[1; 512], [2; 512]]);
+        assert_eq!(receive_opcodes(&listener), vec![OP_OUTPUT, OP_OUTPUT]);
+    }
+
+    /// ArtDmx on `port_address` with `values` on the first channels.
+    fn art_dmx(port_address: u16, values: &[u8]) -> Output {
+        Output {
+            port_address: PortAddress::try_from(port_address).unwrap(),
+            data: values.to_vec().into(),
+            ..Output::default()
+        }
+    }
+
+    #[test]
+    fn art_dmx_merges_by_each_universe_mode() {
+        let config: ArtNetInputConfig = serde_json::from_str(
+            r#"{ "universes": [
+                { "universe": 1, "port_address": 0, "merge": "ltp" },
+                { "universe": 2, "port_address": 1 }
+            ] }"#,
+        )
+        .unwrap();
+        let inputs = input_mappings(Some(config)).unwrap();
+        let show = Mutex::new(Show::new());
+        let local = [0; 512];
+        let merged = |universe, now| show.lock().unwrap().inputs.merge(universe, &local, now)[0];
+        {
+            let mut show = show.lock().unwrap();
+            for (_, universe, merge) in &inputs {
+                show.inputs.set_mode(*universe, *merge);
+            }
+            assert_eq!(show.inputs.mode(1), MergeMode::Ltp);
+            assert_eq!(show.inputs.mode(2), MergeMode::Htp);
+        }
+        // Our own output is older than anything received
+        merged(1, Instant::now());
+
+        let desk: SocketAddr = "10.0.0.1:6454".parse().unwrap();
+        let guest: SocketAddr = "10.0.0.2:6454".parse().unwrap();
+        let send = |source, port_address, value| {
+            merge_art_dmx(&show, &inputs, source, &art_dmx(port_address, &[value]));
+            thread::sleep(Duration::from_millis(2));
+        };
+        for port_address in [0, 1] {
+            send(desk, port_address, 200);
+            send(guest, port_address, 50);
+        }
+        assert_eq!(merged(1, Instant::now()), 50);
+        assert_eq!(merged(2, Instant::now()), 200);
+
+        send(desk, 0, 100);
+        assert_eq!(merged(1, Instant::now()), 100);
+
+        // Not an input port address
+        send(desk, 7, 255);
+        assert_eq!(show.lock().unwrap().inputs.universe_numbers(), [1, 2]);
+
+        let later = Instant::now() + SOURCE_TIMEOUT;
+        assert_eq!(merged(1, later), 0);
+        assert_eq!(merged(2, later), 0);
+    }
+
+    #[test]
+    fn node_from_poll_reply() {
+        let reply = PollReply {
+            address: Ipv4Addr::new(10, 0, 0, 5),
+            version: [1, 2],
+            // Net 1, sub-net 2
+            port_address: [0x01, 0x02],
+            short_name: fill_c_string("Stage left"),
+            long_name: fill_c_string("Stage left 4 port node"),
+            num_ports: [0, 3],
+            // The fourth port is past `num_ports`
+            port_types: [0x80, 0x40, 0xc0, 0x80],
+            swout: [3, 0, 5, 9],
+            swin: [0, 7, 8, 0],
+            mac: [0x00, 0x11, 0x22, 0xaa, 0xbb, 0xcc],
+            bind_index: 2,
+            ..PollReply::default()
+        };
+        let buffer = ArtCommand::PollReply(Box::new(reply))
+            .write_to_buffer()
+            .unwrap();
+        let Ok(ArtCommand::PollReply(reply)) = ArtCommand::from_buffer(&buffer) else {
+            panic!("not a poll reply");
+        };
+
+        let node = ArtNetNode::from_reply(&reply, Instant::now());
+        assert_eq!(node.ip, Ipv4Addr::new(10, 0, 0, 5));
+        assert_eq!(node.short_name, "Stage left");
+        assert_eq!(node.long_name, "Stage left 4 port node");
+        assert_eq!(node.firmware, 0x0102);
+        assert_eq!(node.mac, "00:11:22:aa:bb:cc");
+        assert_eq!(node.bind_index, 2);
+        assert_eq!(node.outputs, [0x123, 0x125]);
+        assert_eq!(node.inputs, [0x127, 0x128]);
+    }
+
+    #[test]
+    fn port_address_parts_are_range_checked() {
+        let parts = |net, subnet, universe| {
+            PortAddressConfig::Parts {
+                net,
+                subnet,
+                universe,
+            }
+            .to_port_address()
+            .map(u16::from)
+        };
+        assert_eq!(parts(1, 2, 3), Ok(0x123));
+        assert_eq!(parts(127, 15, 15), Ok(0x7fff));
+        assert_eq!(
+            parts(128, 0, 0),
+            Err("net 128 is not within 0-127".to_string())
+        );
+        assert_eq!(
+            parts(0, 16, 0),
+            Err("subnet 16 is not within 0-15".to_string())
+        );
+        assert_eq!(
+            parts(0, 0, 16),
+            Err("universe 16 is not within 0-15".to_string())
+        );
+
+        assert_eq!(
+            PortAddressConfig::Number(32767)
+                .to_port_address()
+                .map(u16::from),
+            Ok(32767)
+        );
+        assert!(PortAddressConfig::Number(32768).to_port_address().is_err());
+    }
+
+    #[test]
+    fn output_config_is_checked_on_open() {
+        let open = |config: ArtNetOutputConfig| match config.open(&Show::new()) {
+            Ok(_) => panic!("{:?} opened", config),
+            Err(e) => e,
+        };
+
+        let error = open(ArtNetOutputConfig {
+            destination: "2.255.255".to_string(),
+            ..ArtNetOutputConfig::default()
+        });
+        assert!(matches!(error, ArtNetError::InvalidDestination(ref a, _) if a == "2.255.255"));
+
+        let error = open(ArtNetOutputConfig {
+            bind: "eth0".to_string(),
+            ..ArtNetOutputConfig::default()
+        });
+        assert!(matches!(error, ArtNetError::InvalidBind(ref a, _) if a == "eth0"));
+
+        // A valid address, but not one of ours
+        let error = open(ArtNetOutputConfig {
+            bind: "192.0.2.1".to_string(),
+            ..ArtNetOutputConfig::default()
+        });
+        assert!(matches!(error, ArtNetError::Bind(ref a, _) if a == "192.0.2.1"));
+
+        let error = open(ArtNetOutputConfig {
+            universes: vec![UniverseMapping {
+                universe: 3,
+                port_address: PortAddressConfig::Parts {
+                    net: 0,
+                    subnet: 20,
+                    universe: 0,
+                },
+            }],
+            ..ArtNetOutputConfig::default()
+        });
+        assert_eq!(
+            error.to_string(),
+            "invalid Art-Net port address for universe 3: subnet 20 is not within 0-15"
+        );
+    }
+
+    #[test]
+    fn sync_packet_layout() {
+        let packet = art_sync();
+        assert_eq!(&packet[..8], b"Art-Net\0");
+        assert_eq!(opcode(&packet), OP_SYNC);
+        assert_eq!(packet[10..], [0, 14, 0, 0]);
+    }
 }
@@ -0,0 +1,88 @@
+#[cfg(test)]
+use std::sync::Mutex;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Time source of the render loop, so effects can be stepped by exact
+/// durations in tests.
+pub trait Clock: Send + Sync {
+    /// Time since the clock was created.
+    fn elapsed(&self) -> Duration;
+
+    /// The moment `elapsed` refers to, to compare with the time DMX input
+    /// arrived.
+    fn now(&self) -> Instant;
+
+    /// Block until `elapsed` reaches `deadline`.
+    fn sleep_until(&self, deadline: Duration);
+}
+
+/// Wall clock time.
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        let now = self.elapsed();
+        if deadline > now {
+            thread::sleep(deadline - now);
+        }
+    }
+}
+
+/// A clock that only moves when told to. Sleeping jumps straight to the
+/// deadline, so a render loop on it runs as fast as it can in simulated time.
+#[cfg(test)]
+pub struct SimulatedClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+#[cfg(test)]
+impl SimulatedClock {
+    pub fn new() -> SimulatedClock {
+        SimulatedClock {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for SimulatedClock {
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        let mut elapsed = self.elapsed.lock().unwrap();
+        *elapsed = (*elapsed).max(deadline);
+    }
+}
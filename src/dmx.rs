@@ -1,41 +1,326 @@
 use std::{
-    sync::{Arc, Mutex},
-    thread,
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
-pub fn launch_dmx_send_thread(universe: Arc<Mutex<crate::lib::universe::Universe>>) {
-    std::thread::spawn(move || {
-        println!("Connecting to Serial Device...");
+use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
 
-        let mut port = serialport::new("/dev/ttyUSB0", 250000)
+use crate::output::{DmxOutput, OutputHealth};
+
+/// How frames are put on the serial line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialProtocol {
+    /// Raw FTDI "Open DMX" dongles, the break is generated by toggling the line.
+    #[default]
+    OpenDmx,
+    /// Enttec DMX USB Pro widget, the frame is wrapped in a "Send DMX" message.
+    EnttecPro,
+}
+
+/// A serial DMX interface and the logical universe sent to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerialDmxConfig {
+    /// e.g. `/dev/ttyUSB0`
+    pub device: String,
+    #[serde(default = "default_universe")]
+    pub universe: u16,
+    #[serde(default)]
+    pub protocol: SerialProtocol,
+}
+
+fn default_universe() -> u16 {
+    1
+}
+
+/// Delay before the first reconnect attempt, doubled after every failure.
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Enttec DMX USB Pro message framing.
+const ENTTEC_START: u8 = 0x7e;
+const ENTTEC_END: u8 = 0xe7;
+/// "Output Only Send DMX Packet Request"
+const ENTTEC_LABEL_SEND_DMX: u8 = 6;
+
+/// Wrap `frame` in an Enttec DMX USB Pro "Send DMX" message: start code 0
+/// plus all 512 slots.
+fn enttec_pro_message(frame: &[u8; 512]) -> Vec<u8> {
+    let length = (frame.len() + 1) as u16;
+    let mut message = Vec::with_capacity(frame.len() + 6);
+    message.push(ENTTEC_START);
+    message.push(ENTTEC_LABEL_SEND_DMX);
+    message.extend_from_slice(&length.to_le_bytes());
+    message.push(0x00);
+    message.extend_from_slice(frame);
+    message.push(ENTTEC_END);
+    message
+}
+
+impl SerialDmxConfig {
+    fn open(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        let baud_rate = match self.protocol {
+            SerialProtocol::OpenDmx => 250000,
+            // USB widget, the rate is ignored
+            SerialProtocol::EnttecPro => 57600,
+        };
+        serialport::new(&self.device, baud_rate)
             .data_bits(serialport::DataBits::Eight)
             .parity(serialport::Parity::None)
             .stop_bits(serialport::StopBits::Two)
             .flow_control(serialport::FlowControl::None)
             .timeout(Duration::from_millis(10))
             .open()
-            .expect("Couldnt Open Serial Port");
-
-        println!("Connected to Serial Device");
+    }
+}
 
-        println!("Sending DMX Data...");
-        loop {
+/// Write one DMX frame to `port` in the given protocol.
+fn send_frame(
+    port: &mut dyn SerialPort,
+    protocol: SerialProtocol,
+    frame: &[u8; 512],
+) -> io::Result<()> {
+    match protocol {
+        SerialProtocol::OpenDmx => {
             // Send DMX Break
             port.set_break().ok();
             thread::sleep(Duration::from_micros(120));
             port.clear_break().ok();
             thread::sleep(Duration::from_micros(12));
-            // Copy data from shared Dmx Universe
-            let mut new_channels: [u8; 513] = [0; 513];
-            {
-                new_channels[1..].copy_from_slice(&universe.lock().unwrap().get_dmx_values());
+
+            let mut channels: [u8; 513] = [0; 513];
+            channels[1..].copy_from_slice(frame);
+            port.write_all(&channels)?;
+        }
+        SerialProtocol::EnttecPro => port.write_all(&enttec_pro_message(frame))?,
+    }
+    port.flush()
+}
+
+/// A serial DMX interface. Writing a frame takes most of a DMX frame time,
+/// so a thread of its own writes the latest frame handed to the output and
+/// reopens the port with backoff whenever opening or writing fails.
+pub struct SerialOutput {
+    config: SerialDmxConfig,
+    frame: Arc<Mutex<[u8; 512]>>,
+    health: Arc<Mutex<OutputHealth>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SerialOutput {
+    pub fn new(config: SerialDmxConfig) -> SerialOutput {
+        SerialOutput {
+            config,
+            frame: Arc::new(Mutex::new([0; 512])),
+            health: Arc::new(Mutex::new(OutputHealth::Stopped)),
+            stop: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+}
+
+/// Sleep for `duration`, waking up early when `stop` is set.
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) {
+    let step = Duration::from_millis(50);
+    let mut left = duration;
+    while !left.is_zero() && !stop.load(Ordering::Relaxed) {
+        let nap = left.min(step);
+        thread::sleep(nap);
+        left -= nap;
+    }
+}
+
+impl DmxOutput for SerialOutput {
+    fn name(&self) -> String {
+        format!("Serial {}", self.config.device)
+    }
+
+    fn universes(&self) -> Vec<u16> {
+        vec![self.config.universe]
+    }
+
+    fn start(&mut self) {
+        if self.thread.is_some() {
+            return;
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        *self.health.lock().unwrap() = OutputHealth::Connecting;
+
+        let config = self.config.clone();
+        let frame = self.frame.clone();
+        let health = self.health.clone();
+        let stop = self.stop.clone();
+        self.thread = Some(std::thread::spawn(move || {
+            let set_health = |new: OutputHealth| {
+                let mut health = health.lock().unwrap();
+                // Only log when the state or the error changes, not every retry
+                let changed = match (&*health, &new) {
+                    (
+                        OutputHealth::Reconnecting { error: old, .. },
+                        OutputHealth::Reconnecting { error, .. },
+                    ) => old != error,
+                    (old, new) => old != new,
+                };
+                if changed {
+                    match &new {
+                        OutputHealth::Running => {
+                            println!("🔌 Connected to Serial Device {}", config.device)
+                        }
+                        OutputHealth::Reconnecting { error, retry_in_ms } => eprintln!(
+                            "Serial Device {}: {}, retrying in {} ms",
+                            config.device, error, retry_in_ms
+                        ),
+                        _ => {}
+                    }
+                }
+                *health = new;
+            };
+
+            println!("Connecting to Serial Device {}...", config.device);
+            let mut backoff = MIN_BACKOFF;
+            while !stop.load(Ordering::Relaxed) {
+                let error = match config.open() {
+                    Ok(mut port) => {
+                        set_health(OutputHealth::Running);
+                        backoff = MIN_BACKOFF;
+                        loop {
+                            if stop.load(Ordering::Relaxed) {
+                                return;
+                            }
+                            let frame = *frame.lock().unwrap();
+                            if let Err(e) = send_frame(port.as_mut(), config.protocol, &frame) {
+                                break format!("write failed: {}", e);
+                            }
+                            thread::sleep(Duration::from_millis(25)); // ~40 FPS
+                        }
+                    }
+                    Err(e) => format!("could not open: {}", e),
+                };
+
+                set_health(OutputHealth::Reconnecting {
+                    error,
+                    retry_in_ms: backoff.as_millis() as u64,
+                });
+                sleep_unless_stopped(backoff, &stop);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
             }
-            // write to port
-            port.write_all(&new_channels)
-                .expect("Failed to write DMX data");
-            port.flush().ok();
-            thread::sleep(Duration::from_millis(25)); // ~40 FPS
+        }));
+    }
+
+    fn send_frame(&mut self, _universe: u16, frame: &[u8; 512]) {
+        *self.frame.lock().unwrap() = *frame;
+    }
+
+    fn health(&self) -> OutputHealth {
+        self.health.lock().unwrap().clone()
+    }
+
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            eprintln!("Serial output thread panicked");
         }
-    });
+        *self.health.lock().unwrap() = OutputHealth::Stopped;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, time::Instant};
+
+    use serialport::TTYPort;
+
+    use super::*;
+
+    /// Open the slave end of a pty pair the way the output opens a device,
+    /// returning it with the master end to read from.
+    fn open_pty(protocol: SerialProtocol) -> (Box<dyn SerialPort>, TTYPort) {
+        let (master, slave) = TTYPort::pair().expect("pty pair");
+        let config = SerialDmxConfig {
+            device: slave.name().expect("pty name"),
+            universe: 1,
+            protocol,
+        };
+        let port = config.open().expect("open pty");
+        (port, master)
+    }
+
+    fn read_exactly(master: &mut TTYPort, length: usize) -> Vec<u8> {
+        master
+            .set_timeout(Duration::from_millis(500))
+            .expect("set timeout");
+        let mut buffer = vec![0; length];
+        master.read_exact(&mut buffer).expect("read from pty");
+        buffer
+    }
+
+    fn test_frame() -> [u8; 512] {
+        let mut frame = [0; 512];
+        for (i, value) in frame.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+        frame
+    }
+
+    #[test]
+    fn enttec_pro_framing() {
+        let (mut port, mut master) = open_pty(SerialProtocol::EnttecPro);
+        let frame = test_frame();
+        send_frame(port.as_mut(), SerialProtocol::EnttecPro, &frame).unwrap();
+
+        let message = read_exactly(&mut master, 518);
+        assert_eq!(message[..5], [0x7e, 6, 0x01, 0x02, 0x00]);
+        assert_eq!(message[5..517], frame);
+        assert_eq!(message[517], 0xe7);
+    }
+
+    #[test]
+    fn open_dmx_framing() {
+        let (mut port, mut master) = open_pty(SerialProtocol::OpenDmx);
+        let frame = test_frame();
+        send_frame(port.as_mut(), SerialProtocol::OpenDmx, &frame).unwrap();
+
+        let data = read_exactly(&mut master, 513);
+        assert_eq!(data[0], 0x00);
+        assert_eq!(data[1..], frame);
+    }
+
+    #[test]
+    fn missing_device_reconnects_with_growing_backoff() {
+        let mut output = SerialOutput::new(SerialDmxConfig {
+            device: "/dev/illumix-no-such-device".to_string(),
+            universe: 1,
+            protocol: SerialProtocol::EnttecPro,
+        });
+        output.start();
+
+        // Every failed attempt doubles the wait before the next one
+        let mut retries = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while retries.len() < 2 && Instant::now() < deadline {
+            if let OutputHealth::Reconnecting { error, retry_in_ms } = output.health() {
+                assert!(error.starts_with("could not open"), "{}", error);
+                if retries.last() != Some(&retry_in_ms) {
+                    retries.push(retry_in_ms);
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(retries, [500, 1000]);
+
+        // Stopping doesn't wait for the backoff to run out
+        let stopping = Instant::now();
+        output.stop();
+        assert!(stopping.elapsed() < Duration::from_millis(200));
+        assert_eq!(output.health(), OutputHealth::Stopped);
+    }
 }
@@ -1,7 +1,19 @@
 use std::any::Any;
-use std::sync::{Arc, Mutex};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::time;
 
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lib::{
+        fixture::{Attribute, Fixture, FixtureComponent},
+        preset::PresetValue,
+        selection::Selection,
+        show::Show,
+    },
+    merge::DEFAULT_PRIORITY,
+};
+
 #[derive(Clone, Copy, Debug)]
 pub enum ParColor {
     Cool,
@@ -18,133 +30,176 @@ impl ParColor {
         }
     }
 
-    fn apply(&self, fixture: &mut crate::lib::fixture::Fixture) {
-        // Reset all colors first
-        for comp in fixture.components.iter_mut() {
-            if let crate::lib::fixture::FixtureComponent::CustomValue(cv) = comp {
-                match cv.name.as_str() {
-                    "cool_white" | "warm_white" | "amber" => cv.value = 0,
-                    _ => {}
-                }
-            }
+    /// Starting color of the `index`th fixture of an effect.
+    fn initial(index: usize, offset_pattern: bool) -> ParColor {
+        let color_offset = if offset_pattern { index % 3 } else { 0 };
+        match color_offset {
+            0 => ParColor::Cool,
+            1 => ParColor::Warm,
+            2 => ParColor::Amber,
+            _ => unreachable!(),
         }
+    }
 
-        // Set the active color to full
-        let component_name = match self {
+    /// `CustomValue` channel of white/amber PARs that shows this color.
+    fn channel(&self) -> &'static str {
+        match self {
             ParColor::Cool => "cool_white",
             ParColor::Warm => "warm_white",
             ParColor::Amber => "amber",
-        };
+        }
+    }
+}
 
-        for comp in fixture.components.iter_mut() {
-            if let crate::lib::fixture::FixtureComponent::CustomValue(cv) = comp {
-                if cv.name == component_name {
-                    cv.value = 255;
-                    break;
-                }
-            }
+const PAR_CHANNELS: [&str; 3] = ["cool_white", "warm_white", "amber"];
+
+/// Show `levels` of each color on the white and amber channels of `fixture`.
+/// Colors that are not listed are turned off, fixtures without those
+/// channels are left alone.
+fn show_colors(fixture: &mut Fixture, levels: &[(ParColor, u8)]) {
+    for comp in fixture.components.iter_mut() {
+        if let FixtureComponent::CustomValue(cv) = comp
+            && PAR_CHANNELS.contains(&cv.name.as_str())
+        {
+            cv.value = levels
+                .iter()
+                .filter(|(color, _)| color.channel() == cv.name)
+                .map(|(_, level)| *level)
+                .max()
+                .unwrap_or(0);
+        }
+    }
+}
+
+/// The attributes `show_colors` sets on `fixture`.
+fn color_attributes(fixture: &Fixture) -> Vec<Attribute> {
+    fixture
+        .attributes()
+        .into_iter()
+        .filter(|a| matches!(a, Attribute::Custom(name) if PAR_CHANNELS.contains(&name.as_str())))
+        .collect()
+}
+
+/// Each of `attributes` of the fixtures in `target` that have it.
+fn selected_attributes(
+    show: &Show,
+    target: &Selection,
+    attributes: &[Attribute],
+) -> Vec<(u8, Attribute)> {
+    let mut driven = Vec::new();
+    for fixture in target
+        .fixture_ids(show)
+        .into_iter()
+        .filter_map(|id| show.get_fixture_by_id(id))
+    {
+        let available = fixture.attributes();
+        for attribute in attributes.iter().filter(|a| available.contains(a)) {
+            driven.push((fixture.id, attribute.clone()));
         }
     }
+    driven
 }
 
 pub trait Effect {
-    /// Advance the present by `time_delta` and apply any changes to `universe`.
-    fn tick(&mut self, time_delta: time::Duration, universe: &mut crate::lib::universe::Universe);
+    /// Advance the present by `time_delta` and apply any changes to `show`.
+    fn tick(&mut self, time_delta: time::Duration, show: &mut Show);
+
+    /// The fixture attributes `tick` sets. They are read back after each tick
+    /// and merged with the other playbacks.
+    fn driven(&self, show: &Show) -> Vec<(u8, Attribute)>;
+
+    /// Playbacks with a higher priority win, see `merge::merge`.
+    fn priority(&self) -> u8 {
+        DEFAULT_PRIORITY
+    }
+
+    /// Scale the size of the effect by `level`, 0.0 to 1.0, e.g. from an
+    /// executor fader. Effects without a size ignore it.
+    fn set_size(&mut self, _level: f64) {}
+
+    /// The fixtures the effect acts on, if it was started on a selection.
+    fn target(&self) -> Option<&Selection> {
+        None
+    }
 
     /// Return a shared Any reference for downcasting; the lifetime is tied to &self.
     fn as_any(&self) -> &dyn Any;
 
     /// Allow mutable downcasting from trait object to concrete type.
     fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Finished effects are dropped after their last tick.
+    fn finished(&self) -> bool {
+        false
+    }
 }
 
+/// An effect in the show, with when it was started for LTP.
+pub struct RunningEffect {
+    pub effect: Box<dyn Effect + Send>,
+    pub activated: u64,
+}
+
+/// The current values of `driven` in `show`, to read back what an effect set.
+pub fn current_values(show: &Show, driven: Vec<(u8, Attribute)>) -> Vec<PresetValue> {
+    driven
+        .into_iter()
+        .filter_map(|(fixture_id, attribute)| {
+            let value = show
+                .get_fixture_by_id(fixture_id)?
+                .get_attribute(&attribute)?;
+            Some(PresetValue {
+                fixture_id,
+                attribute,
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Steps the selected fixtures through cool white, warm white and amber on
+/// every beat.
 pub struct ColorSwapEffect {
     pub bpm: f32,
+    pub target: Selection,
     pub offset_pattern: bool,
-    pub fixture_offsets: Vec<f32>,
     pub smooth: bool, // Enable/disable smooth transitions
     accumulated_time: f32,
+    /// Per fixture, in the order of the selection.
     current_colors: Vec<ParColor>,
-    transition_progress: Vec<f32>, // Tracks transition progress per fixture (0.0 to 1.0)
+    /// Fraction of the full color levels in use, see `Effect::set_size`.
+    size_level: f32,
 }
 
 impl ColorSwapEffect {
-    pub fn new(bpm: f32, fixture_count: usize, offset_pattern: bool, smooth: bool) -> Self {
-        let mut fixture_offsets = vec![0.0; fixture_count];
-        let mut current_colors = Vec::with_capacity(fixture_count);
-        let mut transition_progress = vec![1.0; fixture_count];
-
-        for i in 0..fixture_count {
-            let color_offset = if offset_pattern { i % 3 } else { 0 };
-            current_colors.push(match color_offset {
-                0 => ParColor::Cool,
-                1 => ParColor::Warm,
-                2 => ParColor::Amber,
-                _ => unreachable!(),
-            });
-        }
-
+    pub fn new(bpm: f32, target: Selection, offset_pattern: bool, smooth: bool) -> Self {
         ColorSwapEffect {
             bpm,
+            target,
             offset_pattern,
-            fixture_offsets,
             smooth,
             accumulated_time: 0.0,
-            current_colors,
-            transition_progress,
+            current_colors: Vec::new(),
+            size_level: 1.0,
         }
     }
 
+    /// DMX level of a color showing at `fraction`, scaled by the size.
+    fn level(&self, fraction: f32) -> u8 {
+        (fraction * self.size_level * 255.0) as u8
+    }
+
     /// Change the offset pattern at runtime
     pub fn set_offset_pattern(&mut self, offset_pattern: bool) {
         self.offset_pattern = offset_pattern;
-        for i in 0..self.current_colors.len() {
-            let color_offset = if offset_pattern { i % 3 } else { 0 };
-            self.current_colors[i] = match color_offset {
-                0 => ParColor::Cool,
-                1 => ParColor::Warm,
-                2 => ParColor::Amber,
-                _ => unreachable!(),
-            };
+        for (i, color) in self.current_colors.iter_mut().enumerate() {
+            *color = ParColor::initial(i, offset_pattern);
         }
     }
-
-    fn apply_transition(
-        &self,
-        fixture: &mut crate::lib::fixture::Fixture,
-        color: ParColor,
-        intensity: u8,
-    ) {
-        // Helper to find and update a color component
-        let update_component =
-            |name: &str, value: u8, fixture: &mut crate::lib::fixture::Fixture| {
-                for comp in fixture.components.iter_mut() {
-                    if let crate::lib::fixture::FixtureComponent::CustomValue(cv) = comp {
-                        if cv.name == name {
-                            cv.value = value;
-                            break;
-                        }
-                    }
-                }
-            };
-
-        // Reset all colors first
-        update_component("cool_white", 0, fixture);
-        update_component("warm_white", 0, fixture);
-        update_component("amber", 0, fixture);
-
-        // Set the active color
-        let component_name = match color {
-            ParColor::Cool => "cool_white",
-            ParColor::Warm => "warm_white",
-            ParColor::Amber => "amber",
-        };
-        update_component(component_name, intensity, fixture);
-    }
 }
 
 impl Effect for ColorSwapEffect {
-    fn tick(&mut self, time_delta: time::Duration, universe: &mut crate::lib::universe::Universe) {
+    fn tick(&mut self, time_delta: time::Duration, show: &mut Show) {
         let delta_seconds = time_delta.as_secs_f32();
         self.accumulated_time += delta_seconds;
 
@@ -155,15 +210,17 @@ impl Effect for ColorSwapEffect {
         // Detect beat change (synchronized for all fixtures)
         let is_beat_change = cycle_progress < delta_seconds / seconds_per_beat;
 
-        // Add debug print for timing
-        // if is_beat_change {
-        //     println!("Beat change at {} seconds, progress: {}", self.accumulated_time, cycle_progress);
-        // }
+        // Fixtures can join the selection, e.g. when a group is edited
+        let fixture_ids = self.target.fixture_ids(show);
+        while self.current_colors.len() < fixture_ids.len() {
+            let index = self.current_colors.len();
+            self.current_colors
+                .push(ParColor::initial(index, self.offset_pattern));
+        }
 
         // Update each fixture
-        for i in 0..self.fixture_offsets.len() {
-            let fixture_id = 1 + i as u8; // PAR fixtures are 1-7
-            if let Some(fixture) = universe.get_fixture_by_id_mut(fixture_id) {
+        for (i, &fixture_id) in fixture_ids.iter().enumerate() {
+            if let Some(fixture) = show.get_fixture_by_id_mut(fixture_id) {
                 if self.smooth {
                     // Crossfade between current and next color over the beat
                     let mut current_color = self.current_colors[i];
@@ -176,45 +233,48 @@ impl Effect for ColorSwapEffect {
                         current_color = next_color;
                         next_color = current_color.next();
                     }
-                    // Set both colors with crossfade
-                    // Reset all colors first
-                    for comp in fixture.components.iter_mut() {
-                        if let crate::lib::fixture::FixtureComponent::CustomValue(cv) = comp {
-                            match cv.name.as_str() {
-                                "cool_white" | "warm_white" | "amber" => cv.value = 0,
-                                _ => {}
-                            }
-                        }
-                    }
-                    let set_intensity =
-                        |color: ParColor, value: u8, fixture: &mut crate::lib::fixture::Fixture| {
-                            let name = match color {
-                                ParColor::Cool => "cool_white",
-                                ParColor::Warm => "warm_white",
-                                ParColor::Amber => "amber",
-                            };
-                            for comp in fixture.components.iter_mut() {
-                                if let crate::lib::fixture::FixtureComponent::CustomValue(cv) = comp
-                                {
-                                    if cv.name == name {
-                                        cv.value = value;
-                                    }
-                                }
-                            }
-                        };
-                    set_intensity(current_color, ((1.0 - t) * 255.0) as u8, fixture);
-                    set_intensity(next_color, (t * 255.0) as u8, fixture);
+                    show_colors(
+                        fixture,
+                        &[
+                            (current_color, self.level(1.0 - t)),
+                            (next_color, self.level(t)),
+                        ],
+                    );
                 } else {
                     // For non-smooth, change color instantly at beat
                     if is_beat_change {
                         self.current_colors[i] = self.current_colors[i].next();
                     }
-                    self.apply_transition(fixture, self.current_colors[i], 255);
+                    show_colors(fixture, &[(self.current_colors[i], self.level(1.0))]);
                 }
             }
         }
     }
 
+    fn driven(&self, show: &Show) -> Vec<(u8, Attribute)> {
+        let mut driven = Vec::new();
+        for fixture in self
+            .target
+            .fixture_ids(show)
+            .into_iter()
+            .filter_map(|id| show.get_fixture_by_id(id))
+        {
+            for attribute in color_attributes(fixture) {
+                driven.push((fixture.id, attribute));
+            }
+        }
+        driven
+    }
+
+    /// Dims the colors, a color swap has no other size.
+    fn set_size(&mut self, level: f64) {
+        self.size_level = level as f32;
+    }
+
+    fn target(&self) -> Option<&Selection> {
+        Some(&self.target)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -224,14 +284,28 @@ impl Effect for ColorSwapEffect {
     }
 }
 
+/// Runs the RGB component of the selected fixtures through `colors`.
 pub struct GradientEffect {
     pub speed: f32,
     pub colors: Vec<[u8; 3]>,
     pub position: f32,
+    pub target: Selection,
+}
+
+impl GradientEffect {
+    /// Blend through `colors`, `speed` colors per second, starting at the first.
+    pub fn new(speed: f32, target: Selection, colors: Vec<[u8; 3]>) -> Self {
+        GradientEffect {
+            speed,
+            colors,
+            position: 0.0,
+            target,
+        }
+    }
 }
 
 impl Effect for GradientEffect {
-    fn tick(&mut self, time_delta: time::Duration, universe: &mut crate::lib::universe::Universe) {
+    fn tick(&mut self, time_delta: time::Duration, show: &mut Show) {
         // advance the internal position
         let delta_seconds = time_delta.as_secs_f32();
         self.position += self.speed * delta_seconds;
@@ -239,31 +313,40 @@ impl Effect for GradientEffect {
             self.position -= self.colors.len() as f32;
         }
 
-        // apply color to fixture #2 if present
-        if let Some(fixture) = universe.get_fixture_by_id_mut(2) {
-            if let Some(crate::lib::fixture::FixtureComponent::Color(rgb)) = fixture
-                .components
-                .iter_mut()
-                .find(|comp| matches!(comp, crate::lib::fixture::FixtureComponent::Color(_)))
-            {
-                let color_index = self.position.floor() as usize % self.colors.len();
-                let next_color_index = (color_index + 1) % self.colors.len();
-                let t = self.position.fract();
+        let color_index = self.position.floor() as usize % self.colors.len();
+        let next_color_index = (color_index + 1) % self.colors.len();
+        let t = self.position.fract();
 
-                let color1 = self.colors[color_index];
-                let color2 = self.colors[next_color_index];
+        let color1 = self.colors[color_index];
+        let color2 = self.colors[next_color_index];
 
+        // apply the color to every selected fixture with an RGB component
+        for fixture_id in self.target.fixture_ids(show) {
+            if let Some(fixture) = show.get_fixture_by_id_mut(fixture_id)
+                && let Some(FixtureComponent::Color(rgb)) = fixture
+                    .components
+                    .iter_mut()
+                    .find(|comp| matches!(comp, FixtureComponent::Color(_)))
+            {
                 rgb.r = ((1.0 - t) * color1[0] as f32 + t * color2[0] as f32) as u8;
                 rgb.g = ((1.0 - t) * color1[1] as f32 + t * color2[1] as f32) as u8;
                 rgb.b = ((1.0 - t) * color1[2] as f32 + t * color2[2] as f32) as u8;
-                // println!(
-                //     "GradientPresent applied color: r={}, g={}, b={}",
-                //     rgb.r, rgb.g, rgb.b
-                // );
             }
         }
     }
 
+    fn driven(&self, show: &Show) -> Vec<(u8, Attribute)> {
+        selected_attributes(
+            show,
+            &self.target,
+            &[Attribute::Red, Attribute::Green, Attribute::Blue],
+        )
+    }
+
+    fn target(&self) -> Option<&Selection> {
+        Some(&self.target)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -273,40 +356,1009 @@ impl Effect for GradientEffect {
     }
 }
 
-pub fn launch_present_thread(
-    universe: Arc<Mutex<crate::lib::universe::Universe>>,
-    tick_rate: time::Duration,
-) {
-    std::thread::spawn(move || {
-        let mut last_instant = time::Instant::now();
-        loop {
-            let now = time::Instant::now();
-            let delta = now.duration_since(last_instant);
-            last_instant = now;
+/// Fades attributes from their current values to `targets`, e.g. when a
+/// preset is recalled. Color and gobo wheels snap at the start instead.
+pub struct FadeEffect {
+    /// Each target with the value it started from, taken on the first tick.
+    targets: Vec<(PresetValue, Option<u16>)>,
+    duration: time::Duration,
+    elapsed: time::Duration,
+}
 
-            {
-                let mut universe = universe.lock().unwrap();
-                // take presents out so we can mutably borrow the universe while ticking
-                let mut effects = std::mem::take(&mut universe.effects);
-                for effect in effects.iter_mut() {
-                    effect.tick(delta, &mut universe);
-                    //println!("Ticked effect");
+impl FadeEffect {
+    pub fn new(targets: Vec<PresetValue>, duration: time::Duration) -> Self {
+        FadeEffect {
+            targets: targets.into_iter().map(|target| (target, None)).collect(),
+            duration,
+            elapsed: time::Duration::ZERO,
+        }
+    }
+
+    /// Stop fading the attributes `other` takes over.
+    fn release(&mut self, other: &[PresetValue]) {
+        self.targets.retain(|(target, _)| {
+            !other
+                .iter()
+                .any(|o| o.fixture_id == target.fixture_id && o.attribute == target.attribute)
+        });
+    }
+}
+
+impl Effect for FadeEffect {
+    fn tick(&mut self, time_delta: time::Duration, show: &mut Show) {
+        self.elapsed += time_delta;
+        let progress = if self.duration.is_zero() {
+            1.0
+        } else {
+            (self.elapsed.as_secs_f64() / self.duration.as_secs_f64()).min(1.0)
+        };
+
+        for (target, from) in self.targets.iter_mut() {
+            let Some(fixture) = show.get_fixture_by_id_mut(target.fixture_id) else {
+                continue;
+            };
+            let from = *from.get_or_insert_with(|| {
+                fixture
+                    .get_attribute(&target.attribute)
+                    .unwrap_or(target.value)
+            });
+            let value = match target.attribute {
+                Attribute::ColorWheel | Attribute::Gobo => target.value,
+                _ => (from as f64 + (target.value as f64 - from as f64) * progress).round() as u16,
+            };
+            fixture.set_attribute(&target.attribute, value);
+        }
+    }
+
+    fn driven(&self, _show: &Show) -> Vec<(u8, Attribute)> {
+        self.targets
+            .iter()
+            .map(|(target, _)| (target.fixture_id, target.attribute.clone()))
+            .collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// Fade to `targets` over `duration`, taking the attributes over from fades
+/// that are still running.
+pub fn start_fade(show: &mut Show, targets: Vec<PresetValue>, duration: time::Duration) {
+    for running in show.effects.iter_mut() {
+        if let Some(fade) = running.effect.as_any_mut().downcast_mut::<FadeEffect>() {
+            fade.release(&targets);
+        }
+    }
+    show.insert_present(FadeEffect::new(targets, duration));
+}
+
+/// Start or stop effects from the UI, e.g. `"effect": "stop"` or
+/// `"effect": { "gradient": { "target": "pars", "colors": [[255, 0, 0], [0, 0, 255]], "speed": 0.5 } }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EffectCommand {
+    /// Blend the RGB fixtures of `target` through `colors`, `speed` colors
+    /// per second.
+    Gradient {
+        target: Selection,
+        colors: Vec<[u8; 3]>,
+        speed: f32,
+    },
+    /// Run `attribute` of `target` through the full range of `waveform`.
+    Waveform {
+        attribute: Attribute,
+        target: Selection,
+        waveform: Waveform,
+        speed: Speed,
+    },
+    /// Move the heads of `target` along `shape`, `size` degrees around
+    /// `center` (pan, tilt).
+    Position {
+        shape: Shape,
+        target: Selection,
+        center: (f64, f64),
+        size: f64,
+        speed: Speed,
+    },
+    /// Stop every effect but the preset fades. The fixtures keep the values
+    /// the effects last set.
+    Stop,
+}
+
+/// Run `command` on the effects of `show`. Returns false if it can't start
+/// the effect.
+pub fn command(show: &mut Show, command: EffectCommand) -> bool {
+    match command {
+        EffectCommand::Gradient {
+            target,
+            colors,
+            speed,
+        } => {
+            if colors.is_empty() {
+                return false;
+            }
+            show.insert_present(GradientEffect::new(speed, target, colors));
+        }
+        EffectCommand::Waveform {
+            attribute,
+            target,
+            waveform,
+            speed,
+        } => show.insert_present(WaveformEffect::new(attribute, target, waveform, speed)),
+        EffectCommand::Position {
+            shape,
+            target,
+            center,
+            size,
+            speed,
+        } => show.insert_present(PositionEffect::new(shape, target, center, size, speed)),
+        EffectCommand::Stop => show
+            .effects
+            .retain(|running| running.effect.as_any().is::<FadeEffect>()),
+    }
+    true
+}
+
+/// Shape of one cycle of a `WaveformEffect`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Waveform {
+    /// Starts at the bottom, peaks half way through the cycle.
+    Sine,
+    /// Rises over the cycle, then drops back.
+    Saw,
+    /// Top for the first half of the cycle, bottom for the second.
+    Square,
+    /// Rises for the first half of the cycle, falls for the second.
+    Triangle,
+    /// A new random level every cycle, different for each fixture.
+    Random,
+}
+
+impl Waveform {
+    /// Level from 0.0 to 1.0 at `phase` (0.0 to 1.0) of cycle number `cycle`.
+    /// `seed` tells fixtures apart for `Random`.
+    pub fn sample(&self, phase: f64, cycle: i64, seed: usize) -> f64 {
+        match self {
+            Waveform::Sine => 0.5 - 0.5 * (phase * std::f64::consts::TAU).cos(),
+            Waveform::Saw => phase,
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    0.0
                 }
-                universe.effects = effects;
-                // After effects run, enforce global dimmer rules:
-                // - For every fixture with a Dimmer component, compute effective intensity
-                //   as (local * global_dimmer) / 255.
-                let global = universe.global_dimmer;
-                for fixture in universe.fixtures.iter_mut() {
-                    for comp in fixture.components.iter_mut() {
-                        if let crate::lib::fixture::FixtureComponent::Dimmer(d) = comp {
-                            d.intensity = ((d.local as u16 * global as u16) / 255) as u8;
-                        }
-                    }
+            }
+            Waveform::Triangle => 1.0 - (2.0 * phase - 1.0).abs(),
+            Waveform::Random => {
+                let mut hasher = DefaultHasher::new();
+                (cycle, seed).hash(&mut hasher);
+                (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+            }
+        }
+    }
+}
+
+/// How fast a `WaveformEffect` cycles, e.g. `{ "bpm": 120 }` or `{ "hz": 0.5 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Speed {
+    /// Cycles per minute.
+    Bpm(f64),
+    /// Cycles per second.
+    Hz(f64),
+}
+
+impl Speed {
+    pub fn cycles_per_second(&self) -> f64 {
+        match self {
+            Speed::Bpm(bpm) => bpm / 60.0,
+            Speed::Hz(hz) => *hz,
+        }
+    }
+}
+
+/// Modulates one attribute of the selected fixtures with a waveform.
+///
+/// The value is `base + size * level`, as a fraction of the attribute's full
+/// range. `phase_spread` (in degrees) is shared out over the selection so
+/// fixtures follow each other. The selection can be split into `wings` that
+/// mirror each other, and `blocks` of neighbouring fixtures move together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaveformEffect {
+    pub attribute: Attribute,
+    pub target: Selection,
+    pub waveform: Waveform,
+    #[serde(default)]
+    pub base: f64,
+    #[serde(default = "default_size")]
+    pub size: f64,
+    pub speed: Speed,
+    #[serde(default)]
+    pub phase_spread: f64,
+    #[serde(default = "default_grouping")]
+    pub wings: usize,
+    #[serde(default = "default_grouping")]
+    pub blocks: usize,
+    #[serde(default)]
+    pub priority: u8,
+    /// Fraction of `size` in use, see `Effect::set_size`.
+    #[serde(skip, default = "default_size")]
+    size_level: f64,
+    /// Time the effect has run for, in cycles.
+    #[serde(skip)]
+    position: f64,
+}
+
+fn default_size() -> f64 {
+    1.0
+}
+
+fn default_grouping() -> usize {
+    1
+}
+
+impl WaveformEffect {
+    /// Full range (base 0, size 1) at `speed`, no phase spread or grouping.
+    pub fn new(attribute: Attribute, target: Selection, waveform: Waveform, speed: Speed) -> Self {
+        WaveformEffect {
+            attribute,
+            target,
+            waveform,
+            base: 0.0,
+            size: default_size(),
+            speed,
+            phase_spread: 0.0,
+            wings: default_grouping(),
+            blocks: default_grouping(),
+            priority: DEFAULT_PRIORITY,
+            size_level: default_size(),
+            position: 0.0,
+        }
+    }
+
+    /// Phase offset in cycles of the `index`th of `count` selected fixtures.
+    fn phase_offset(&self, index: usize, count: usize) -> f64 {
+        let wings = self.wings.clamp(1, count.max(1));
+        let per_wing = count.div_ceil(wings);
+        let mut position = index % per_wing;
+        // Every other wing runs the other way round
+        if (index / per_wing) % 2 == 1 {
+            position = per_wing - 1 - position;
+        }
+        let blocks = self.blocks.max(1);
+        let steps = per_wing.div_ceil(blocks);
+        self.phase_spread / 360.0 * (position / blocks) as f64 / steps as f64
+    }
+
+    /// Value of the `index`th of `count` selected fixtures, 0.0 to 1.0.
+    pub fn value(&self, index: usize, count: usize) -> f64 {
+        let position = self.position - self.phase_offset(index, count);
+        let level = self
+            .waveform
+            .sample(position.rem_euclid(1.0), position.floor() as i64, index);
+        (self.base + self.size * self.size_level * level).clamp(0.0, 1.0)
+    }
+}
+
+impl Effect for WaveformEffect {
+    fn tick(&mut self, time_delta: time::Duration, show: &mut Show) {
+        self.position += time_delta.as_secs_f64() * self.speed.cycles_per_second();
+
+        let fixture_ids = self.target.fixture_ids(show);
+        for (i, &fixture_id) in fixture_ids.iter().enumerate() {
+            let value = (self.value(i, fixture_ids.len()) * 65535.0).round() as u16;
+            if let Some(fixture) = show.get_fixture_by_id_mut(fixture_id) {
+                fixture.set_attribute(&self.attribute, value);
+            }
+        }
+    }
+
+    fn driven(&self, show: &Show) -> Vec<(u8, Attribute)> {
+        selected_attributes(show, &self.target, std::slice::from_ref(&self.attribute))
+    }
+
+    fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    fn set_size(&mut self, level: f64) {
+        self.size_level = level;
+    }
+
+    fn target(&self) -> Option<&Selection> {
+        Some(&self.target)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Path a `PositionEffect` moves the heads along.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shape {
+    Circle,
+    FigureEight,
+    /// Back and forth along the pan axis.
+    Sweep,
+    /// Glides to a new random point within `size` every cycle.
+    Ballyhoo,
+}
+
+impl Shape {
+    /// Offset from the center at `phase` (0.0 to 1.0) of cycle number
+    /// `cycle`, -1.0 to 1.0 on both axes. `seed` tells heads apart for
+    /// `Ballyhoo`.
+    pub fn offset(&self, phase: f64, cycle: i64, seed: usize) -> (f64, f64) {
+        let angle = phase * std::f64::consts::TAU;
+        match self {
+            Shape::Circle => (angle.sin(), angle.cos()),
+            Shape::FigureEight => (angle.sin(), (2.0 * angle).sin()),
+            Shape::Sweep => (angle.sin(), 0.0),
+            Shape::Ballyhoo => {
+                let point = |cycle: i64| {
+                    let level = |axis: usize| Waveform::Random.sample(0.0, cycle, seed * 2 + axis);
+                    (level(0) * 2.0 - 1.0, level(1) * 2.0 - 1.0)
+                };
+                let (from, to) = (point(cycle), point(cycle + 1));
+                // Ease in and out so the heads don't jerk at each point
+                let t = 0.5 - 0.5 * (phase * std::f64::consts::PI).cos();
+                (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+            }
+        }
+    }
+}
+
+/// Convert `degrees` into a 16 bit DMX value for an axis covering `range`
+/// degrees, clamped to the range.
+pub fn degree_to_16_bit(degrees: f64, range: f64) -> u16 {
+    ((degrees / range).clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+/// Moves the selected heads along a `Shape`.
+///
+/// `center_pan`/`center_tilt` and `size` (the width of the shape) are in
+/// degrees, converted with each head's own pan and tilt range.
+/// `phase_spread` (in degrees) is shared out over the heads so they follow
+/// each other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionEffect {
+    pub shape: Shape,
+    pub target: Selection,
+    pub center_pan: f64,
+    pub center_tilt: f64,
+    pub size: f64,
+    pub speed: Speed,
+    #[serde(default)]
+    pub phase_spread: f64,
+    #[serde(default)]
+    pub priority: u8,
+    /// Fraction of `size` in use, see `Effect::set_size`.
+    #[serde(skip, default = "default_size")]
+    size_level: f64,
+    /// Time the effect has run for, in cycles.
+    #[serde(skip)]
+    position: f64,
+}
+
+impl PositionEffect {
+    /// Shape of `size` degrees around the center, the heads in unison.
+    pub fn new(
+        shape: Shape,
+        target: Selection,
+        center: (f64, f64),
+        size: f64,
+        speed: Speed,
+    ) -> Self {
+        PositionEffect {
+            shape,
+            target,
+            center_pan: center.0,
+            center_tilt: center.1,
+            size,
+            speed,
+            phase_spread: 0.0,
+            priority: DEFAULT_PRIORITY,
+            size_level: default_size(),
+            position: 0.0,
+        }
+    }
+
+    /// Pan and tilt in degrees of the `index`th of `count` selected heads.
+    pub fn degrees(&self, index: usize, count: usize) -> (f64, f64) {
+        let offset = self.phase_spread / 360.0 * index as f64 / count.max(1) as f64;
+        let position = self.position - offset;
+        let (pan, tilt) =
+            self.shape
+                .offset(position.rem_euclid(1.0), position.floor() as i64, index);
+        let radius = self.size * self.size_level / 2.0;
+        (
+            self.center_pan + pan * radius,
+            self.center_tilt + tilt * radius,
+        )
+    }
+}
+
+impl Effect for PositionEffect {
+    fn tick(&mut self, time_delta: time::Duration, show: &mut Show) {
+        self.position += time_delta.as_secs_f64() * self.speed.cycles_per_second();
+
+        let fixture_ids = self.target.fixture_ids(show);
+        for (i, &fixture_id) in fixture_ids.iter().enumerate() {
+            let (pan, tilt) = self.degrees(i, fixture_ids.len());
+            if let Some(fixture) = show.get_fixture_by_id_mut(fixture_id) {
+                let (pan_range, tilt_range) = fixture.pan_tilt_range();
+                fixture.set_attribute(&Attribute::Pan, degree_to_16_bit(pan, pan_range));
+                fixture.set_attribute(&Attribute::Tilt, degree_to_16_bit(tilt, tilt_range));
+            }
+        }
+    }
+
+    fn driven(&self, show: &Show) -> Vec<(u8, Attribute)> {
+        selected_attributes(show, &self.target, &[Attribute::Pan, Attribute::Tilt])
+    }
+
+    fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    fn set_size(&mut self, level: f64) {
+        self.size_level = level;
+    }
+
+    fn target(&self) -> Option<&Selection> {
+        Some(&self.target)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use super::*;
+    use crate::{
+        clock::{Clock, SimulatedClock},
+        lib::preset::PresetValue,
+        lib::show::Show,
+        patching::get_show,
+        render::{Frame, Renderer, render},
+        show_file::ShowFile,
+    };
+
+    const STEP: time::Duration = time::Duration::from_millis(250);
+
+    /// Cool white, warm white and amber of PAR `id`, 1-7.
+    fn par_colors(frame: &Frame, id: usize) -> [u8; 3] {
+        let address = (id - 1) * 6;
+        let dmx = frame.universe(1);
+        [dmx[address], dmx[address + 1], dmx[address + 2]]
+    }
+
+    /// Advance the clock by one step and render the demo show's universe.
+    fn step(clock: &SimulatedClock, renderer: &mut Renderer, show: &mut Show) -> Frame {
+        clock.advance(STEP);
+        renderer.step(show, &[1])
+    }
+
+    fn setup(effect: ColorSwapEffect) -> (Arc<SimulatedClock>, Renderer, Show) {
+        let clock = Arc::new(SimulatedClock::new());
+        let renderer = Renderer::new(clock.clone());
+        let mut show = get_show();
+        show.insert_present(effect);
+        (clock, renderer, show)
+    }
+
+    #[test]
+    fn color_swap_changes_color_on_each_beat() {
+        // 60 BPM: one beat per second, four steps per beat
+        let (clock, mut renderer, mut show) =
+            setup(ColorSwapEffect::new(60.0, "pars".into(), true, false));
+
+        let mut colors = Vec::new();
+        for _ in 0..12 {
+            let frame = step(&clock, &mut renderer, &mut show);
+            colors.push((par_colors(&frame, 1), par_colors(&frame, 2)));
+        }
+        assert_eq!(clock.elapsed(), time::Duration::from_secs(3));
+
+        let (cool, warm, amber) = ([255, 0, 0], [0, 255, 0], [0, 0, 255]);
+        // The offset pattern starts PAR 2 one color ahead of PAR 1
+        assert_eq!(colors[..3], [(cool, warm); 3]);
+        assert_eq!(colors[3..7], [(warm, amber); 4]);
+        assert_eq!(colors[7..11], [(amber, cool); 4]);
+        assert_eq!(colors[11], (cool, warm));
+    }
+
+    #[test]
+    fn color_swap_runs_on_the_shipped_demo_show() {
+        let show_file = ShowFile::load(Path::new("shows/demo.json")).unwrap();
+        let mut show = show_file.to_show().unwrap();
+        assert_eq!(show.groups, get_show().groups);
+
+        let clock = Arc::new(SimulatedClock::new());
+        let mut renderer = Renderer::new(clock.clone());
+        show.insert_present(ColorSwapEffect::new(60.0, "pars".into(), true, false));
+        let frame = step(&clock, &mut renderer, &mut show);
+        assert_eq!(par_colors(&frame, 1), [255, 0, 0]);
+        assert_eq!(par_colors(&frame, 7), [255, 0, 0]);
+    }
+
+    #[test]
+    fn color_swap_crossfades_over_the_beat() {
+        let (clock, mut renderer, mut show) =
+            setup(ColorSwapEffect::new(60.0, "pars".into(), false, true));
+
+        let fades: Vec<[u8; 3]> = (0..5)
+            .map(|_| par_colors(&step(&clock, &mut renderer, &mut show), 1))
+            .collect();
+
+        assert_eq!(
+            fades,
+            [
+                [191, 63, 0],
+                [127, 127, 0],
+                [63, 191, 0],
+                // The beat: warm is now the color fading out
+                [0, 255, 0],
+                [0, 191, 63],
+            ]
+        );
+    }
+
+    #[test]
+    fn global_dimmer_scales_local_dimmers() {
+        let (clock, mut renderer, mut show) =
+            setup(ColorSwapEffect::new(60.0, "pars".into(), false, false));
+        let dimmer = |frame: &Frame| frame.universe(1)[5];
+
+        assert_eq!(dimmer(&step(&clock, &mut renderer, &mut show)), 255);
+
+        show.global_dimmer = 128;
+        assert_eq!(dimmer(&step(&clock, &mut renderer, &mut show)), 128);
+
+        for fixture in show.fixtures_mut() {
+            for comp in fixture.components.iter_mut() {
+                if let FixtureComponent::Dimmer(d) = comp {
+                    d.local = 100;
                 }
             }
+        }
+        assert_eq!(dimmer(&step(&clock, &mut renderer, &mut show)), 50);
+
+        show.global_dimmer = 0;
+        assert_eq!(dimmer(&step(&clock, &mut renderer, &mut show)), 0);
+    }
+
+    #[test]
+    fn instances_drive_their_own_selection() {
+        let (clock, mut renderer, mut show) = setup(ColorSwapEffect::new(
+            60.0,
+            Selection::Fixtures(vec![1, 2]),
+            false,
+            false,
+        ));
+        show.groups.insert("back".to_string(), vec![5, 6]);
+        // Half a beat behind, so it swaps between the first one's beats
+        let mut back = ColorSwapEffect::new(60.0, "back".into(), false, false);
+        back.tick(time::Duration::from_millis(500), &mut show);
+        show.insert_present(back);
+
+        let mut frame = step(&clock, &mut renderer, &mut show);
+        for _ in 0..2 {
+            frame = step(&clock, &mut renderer, &mut show);
+        }
+
+        // 0.75 s: the back group had its beat, the front one not yet
+        assert_eq!(par_colors(&frame, 1), [255, 0, 0]);
+        assert_eq!(par_colors(&frame, 2), [255, 0, 0]);
+        assert_eq!(par_colors(&frame, 3), [0, 0, 0]);
+        assert_eq!(par_colors(&frame, 5), [0, 255, 0]);
+        assert_eq!(par_colors(&frame, 6), [0, 255, 0]);
+    }
+
+    #[test]
+    fn color_swap_only_drives_white_and_amber_channels() {
+        let (clock, mut renderer, mut show) = setup(ColorSwapEffect::new(
+            60.0,
+            Selection::Fixtures(vec![1, 30]),
+            false,
+            false,
+        ));
+        let mut rgb_par = Fixture::new(30, 200, "RGB PAR".to_string());
+        rgb_par.add_component(FixtureComponent::Color(crate::lib::fixture::Color {
+            r: 10,
+            g: 20,
+            b: 30,
+        }));
+        show.add_fixture(rgb_par).unwrap();
+
+        let frame = step(&clock, &mut renderer, &mut show);
+        assert_eq!(par_colors(&frame, 1), [255, 0, 0]);
+        // No colors are made up for a fixture without the PAR channels
+        assert_eq!(frame.universe(1)[199..202], [10, 20, 30]);
+        let driven = show.effects[0].effect.driven(&show);
+        assert!(driven.iter().all(|(fixture_id, _)| *fixture_id == 1));
+    }
+
+    /// Add RGB PARs at `ids` to `show`, three channels each from address 200.
+    fn add_rgb_pars(show: &mut Show, ids: &[u8]) {
+        for (i, &id) in ids.iter().enumerate() {
+            let mut rgb_par = Fixture::new(id, 200 + i as u16 * 3, "RGB PAR".to_string());
+            rgb_par.add_component(FixtureComponent::Color(
+                crate::lib::fixture::Color::default(),
+            ));
+            show.add_fixture(rgb_par).unwrap();
+        }
+    }
+
+    #[test]
+    fn gradient_blends_through_its_colors() {
+        let clock = Arc::new(SimulatedClock::new());
+        let mut renderer = Renderer::new(clock.clone());
+        let mut show = get_show();
+        add_rgb_pars(&mut show, &[30, 31]);
+        // Red to blue and back, one color per second
+        show.insert_present(GradientEffect::new(
+            1.0,
+            Selection::Fixtures(vec![30, 31]),
+            vec![[255, 0, 0], [0, 0, 255]],
+        ));
+
+        let mut colors = Vec::new();
+        for _ in 0..8 {
+            let frame = step(&clock, &mut renderer, &mut show);
+            let dmx = frame.universe(1);
+            assert_eq!(dmx[199..202], dmx[202..205]);
+            colors.push([dmx[199], dmx[200], dmx[201]]);
+        }
+        assert_eq!(clock.elapsed(), time::Duration::from_secs(2));
+
+        assert_eq!(
+            colors,
+            [
+                [191, 0, 63],
+                [127, 0, 127],
+                [63, 0, 191],
+                [0, 0, 255],
+                [63, 0, 191],
+                [127, 0, 127],
+                [191, 0, 63],
+                [255, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn effect_commands_start_and_stop_effects() {
+        let mut show = get_show();
+        add_rgb_pars(&mut show, &[30]);
+        start_fade(
+            &mut show,
+            vec![PresetValue {
+                fixture_id: 8,
+                attribute: Attribute::Pan,
+                value: 0,
+            }],
+            time::Duration::from_secs(1),
+        );
+
+        let gradient = serde_json::from_str(
+            r#"{ "gradient": { "target": [30], "colors": [[0, 255, 0]], "speed": 1 } }"#,
+        )
+        .unwrap();
+        assert!(command(&mut show, gradient));
+        let no_colors = EffectCommand::Gradient {
+            target: Selection::Fixtures(vec![30]),
+            colors: Vec::new(),
+            speed: 1.0,
+        };
+        assert!(!command(&mut show, no_colors));
+        let waveform = serde_json::from_str(
+            r#"{ "waveform": { "attribute": "tilt", "target": "moving_heads",
+                 "waveform": "square", "speed": { "bpm": 60 } } }"#,
+        )
+        .unwrap();
+        assert!(command(&mut show, waveform));
+        let position = serde_json::from_str(
+            r#"{ "position": { "shape": "sweep", "target": [9], "center": [270, 135],
+                 "size": 90, "speed": { "hz": 0.5 } } }"#,
+        )
+        .unwrap();
+        assert!(command(&mut show, position));
+        assert_eq!(show.effects.len(), 4);
+        let frame = render(
+            &mut show,
+            time::Duration::ZERO,
+            &[1],
+            1,
+            time::Instant::now(),
+        );
+        assert_eq!(frame.universe(1)[199..202], [0, 255, 0]);
+        // The square starts at the top of the tilt range
+        assert_eq!(frame.universe(1)[101..103], [255, 255]);
+        // The sweep starts in the center, over the waveform on head 9
+        assert_eq!(frame.universe(1)[115..119], [128, 0, 128, 0]);
+
+        assert!(command(
+            &mut show,
+            serde_json::from_str(r#""stop""#).unwrap()
+        ));
+        assert_eq!(show.effects.len(), 1);
+        assert!(show.effects[0].effect.as_any().is::<FadeEffect>());
+    }
+
+    #[test]
+    fn waveform_shapes() {
+        let sample =
+            |waveform: Waveform| [0.0, 0.25, 0.5, 0.75].map(|phase| waveform.sample(phase, 0, 0));
+        let sine = sample(Waveform::Sine);
+        assert!(sine[0].abs() < 1e-9 && (sine[2] - 1.0).abs() < 1e-9);
+        assert!((sine[1] - 0.5).abs() < 1e-9 && (sine[3] - 0.5).abs() < 1e-9);
+        assert_eq!(sample(Waveform::Saw), [0.0, 0.25, 0.5, 0.75]);
+        assert_eq!(sample(Waveform::Square), [1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(sample(Waveform::Triangle), [0.0, 0.5, 1.0, 0.5]);
+
+        // Random holds its level over a cycle and differs between fixtures
+        let random = Waveform::Random;
+        assert_eq!(random.sample(0.1, 3, 0), random.sample(0.9, 3, 0));
+        assert_ne!(random.sample(0.1, 3, 0), random.sample(0.1, 4, 0));
+        assert_ne!(random.sample(0.1, 3, 0), random.sample(0.1, 3, 1));
+        assert!((0.0..1.0).contains(&random.sample(0.5, 7, 2)));
+    }
+
+    #[test]
+    fn waveform_spreads_phase_over_the_selection() {
+        let clock = Arc::new(SimulatedClock::new());
+        let mut renderer = Renderer::new(clock.clone());
+        let mut show = get_show();
+        // Saw on the PAR dimmers, one full cycle spread over the four PARs
+        let mut effect = WaveformEffect::new(
+            Attribute::Dimmer,
+            Selection::Fixtures(vec![1, 2, 3, 4]),
+            Waveform::Saw,
+            Speed::Hz(1.0),
+        );
+        effect.phase_spread = 360.0;
+        show.insert_present(effect);
+
+        let dimmers = |frame: &Frame| [1, 2, 3, 4, 5].map(|id| frame.universe(1)[(id - 1) * 6 + 5]);
+        let frame = step(&clock, &mut renderer, &mut show);
+        assert_eq!(dimmers(&frame), [63, 0, 191, 127, 255]);
+        let frame = step(&clock, &mut renderer, &mut show);
+        assert_eq!(dimmers(&frame), [127, 63, 0, 191, 255]);
+    }
+
+    #[test]
+    fn waveform_wings_and_blocks() {
+        let mut effect = WaveformEffect::new(
+            Attribute::Dimmer,
+            Selection::Fixtures((1..=8).collect()),
+            Waveform::Saw,
+            Speed::Bpm(60.0),
+        );
+        effect.phase_spread = 360.0;
+        let offsets = |effect: &WaveformEffect| {
+            (0..8)
+                .map(|i| effect.phase_offset(i, 8))
+                .collect::<Vec<f64>>()
+        };
+
+        effect.wings = 2;
+        assert_eq!(
+            offsets(&effect),
+            [0.0, 0.25, 0.5, 0.75, 0.75, 0.5, 0.25, 0.0]
+        );
+
+        effect.wings = 1;
+        effect.blocks = 2;
+        assert_eq!(
+            offsets(&effect),
+            [0.0, 0.0, 0.25, 0.25, 0.5, 0.5, 0.75, 0.75]
+        );
+    }
+
+    #[test]
+    fn waveform_drives_16_bit_pan_with_base_and_size() {
+        let mut show = get_show();
+        // Square between 25% and 75% of the pan range on the moving heads
+        let mut effect = WaveformEffect::new(
+            Attribute::Pan,
+            "moving_heads".into(),
+            Waveform::Square,
+            Speed::Hz(1.0),
+        );
+        effect.base = 0.25;
+        effect.size = 0.5;
+
+        effect.tick(time::Duration::from_millis(250), &mut show);
+        let frame = render(
+            &mut show,
+            time::Duration::ZERO,
+            &[1],
+            1,
+            time::Instant::now(),
+        );
+        let pan = |frame: &Frame, address: usize| {
+            u16::from_be_bytes([frame.universe(1)[address - 1], frame.universe(1)[address]])
+        };
+        assert_eq!(pan(&frame, 100), 49151);
+        assert_eq!(pan(&frame, 116), 49151);
+
+        effect.tick(time::Duration::from_millis(500), &mut show);
+        let frame = render(
+            &mut show,
+            time::Duration::ZERO,
+            &[1],
+            2,
+            time::Instant::now(),
+        );
+        assert_eq!(pan(&frame, 100), 16384);
+    }
+
+    /// Pan and tilt of the moving head at `address` in universe 1.
+    fn pan_tilt(show: &Show, address: usize) -> (u16, u16) {
+        let dmx = render(
+            &mut show.clone(),
+            time::Duration::ZERO,
+            &[1],
+            1,
+            time::Instant::now(),
+        )
+        .universe(1);
+        (
+            u16::from_be_bytes([dmx[address - 1], dmx[address]]),
+            u16::from_be_bytes([dmx[address + 1], dmx[address + 2]]),
+        )
+    }
+
+    #[test]
+    fn circle_converts_degrees_with_each_heads_range() {
+        let mut show = get_show();
+        // Head 9 only turns 360 degrees on pan and 180 on tilt
+        for comp in show.get_fixture_by_id_mut(9).unwrap().components.iter_mut() {
+            if let FixtureComponent::Position(p) = comp {
+                p.pan_range = Some(360.0);
+                p.tilt_range = Some(180.0);
+            }
+        }
+        let mut effect = PositionEffect::new(
+            Shape::Circle,
+            "moving_heads".into(),
+            (180.0, 90.0),
+            60.0,
+            Speed::Hz(1.0),
+        );
+
+        // Top of the circle, then a quarter turn further
+        effect.tick(time::Duration::ZERO, &mut show);
+        assert_eq!(
+            pan_tilt(&show, 100),
+            (21845, degree_to_16_bit(120.0, 270.0))
+        );
+        assert_eq!(pan_tilt(&show, 116), (32768, 43690));
+
+        effect.tick(time::Duration::from_millis(250), &mut show);
+        assert_eq!(
+            pan_tilt(&show, 100),
+            (degree_to_16_bit(210.0, 540.0), 21845)
+        );
+        assert_eq!(
+            pan_tilt(&show, 116),
+            (degree_to_16_bit(210.0, 360.0), 32768)
+        );
+    }
 
-            std::thread::sleep(tick_rate);
+    #[test]
+    fn shapes_stay_within_size() {
+        for shape in [
+            Shape::Circle,
+            Shape::FigureEight,
+            Shape::Sweep,
+            Shape::Ballyhoo,
+        ] {
+            for step in 0..200 {
+                let phase = step as f64 / 50.0;
+                let (pan, tilt) = shape.offset(phase.fract(), phase.floor() as i64, 3);
+                assert!(
+                    pan.abs() <= 1.0 + 1e-9 && tilt.abs() <= 1.0 + 1e-9,
+                    "{:?}",
+                    shape
+                );
+            }
         }
-    });
+        // The figure-8 crosses the center twice per cycle
+        let (pan, tilt) = Shape::FigureEight.offset(0.5, 0, 0);
+        assert!(pan.abs() < 1e-9 && tilt.abs() < 1e-9);
+        // Sweeps stay on the tilt center
+        assert_eq!(Shape::Sweep.offset(0.3, 0, 0).1, 0.0);
+    }
+
+    #[test]
+    fn ballyhoo_glides_between_random_points() {
+        let shape = Shape::Ballyhoo;
+        // Continuous over the cycle boundary, ending where the next starts
+        let end = shape.offset(1.0, 4, 0);
+        let start = shape.offset(0.0, 5, 0);
+        assert!((end.0 - start.0).abs() < 1e-9 && (end.1 - start.1).abs() < 1e-9);
+        // Each head picks its own points
+        assert_ne!(shape.offset(0.0, 5, 0), shape.offset(0.0, 5, 1));
+    }
+
+    #[test]
+    fn phase_spread_offsets_heads() {
+        let mut effect = PositionEffect::new(
+            Shape::Sweep,
+            "moving_heads".into(),
+            (270.0, 135.0),
+            90.0,
+            Speed::Bpm(60.0),
+        );
+        effect.phase_spread = 180.0;
+        effect.position = 0.25;
+
+        // Half a cycle over two heads: the second is a quarter cycle behind
+        assert_eq!(effect.degrees(0, 2), (315.0, 135.0));
+        let (pan, tilt) = effect.degrees(1, 2);
+        assert!((pan - 270.0).abs() < 1e-9 && tilt == 135.0);
+    }
+
+    #[test]
+    fn fade_reaches_targets_and_finishes() {
+        let clock = Arc::new(SimulatedClock::new());
+        let mut renderer = Renderer::new(clock.clone());
+        let mut show = get_show();
+        let target = |fixture_id, attribute, value| PresetValue {
+            fixture_id,
+            attribute,
+            value,
+        };
+        start_fade(
+            &mut show,
+            vec![
+                target(1, Attribute::Dimmer, 0),
+                target(8, Attribute::ColorWheel, 40 * 257),
+            ],
+            time::Duration::from_secs(1),
+        );
+
+        let levels = |frame: &Frame| (frame.universe(1)[5], frame.universe(1)[103]);
+        assert_eq!(levels(&step(&clock, &mut renderer, &mut show)), (191, 40));
+        assert_eq!(levels(&step(&clock, &mut renderer, &mut show)), (127, 40));
+
+        // A new fade takes the dimmer over from where it is
+        start_fade(
+            &mut show,
+            vec![target(1, Attribute::Dimmer, 65535)],
+            time::Duration::from_millis(500),
+        );
+        assert_eq!(levels(&step(&clock, &mut renderer, &mut show)), (191, 40));
+        assert_eq!(show.effects.len(), 2);
+        assert_eq!(levels(&step(&clock, &mut renderer, &mut show)), (255, 40));
+
+        // Both fades are done and dropped
+        assert!(show.effects.is_empty());
+    }
 }
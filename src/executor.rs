@@ -0,0 +1,455 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    effect::{Effect, PositionEffect, WaveformEffect, current_values},
+    lib::{
+        cue::{Cue, CueCommand, CueList},
+        fixture::Attribute,
+        preset::PresetValue,
+        show::Show,
+    },
+    merge::{self, Layer, is_htp},
+};
+
+/// What the fader of an effect executor changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaderMode {
+    #[default]
+    Size,
+    Speed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutorCommand {
+    /// Start the playback, or the next cue of a cue list.
+    Go,
+    /// Stop the playback and give its fixtures back.
+    Stop,
+    /// Full level while held: `true` on press, `false` on release.
+    Flash(bool),
+    /// Fader level, 0-255.
+    Level(u8),
+}
+
+/// What an executor plays back.
+pub enum Playback {
+    /// A stored preset, looked up every frame so storing it again shows at
+    /// once.
+    Preset(u32),
+    CueList(CueList),
+    Effect(Box<dyn Effect + Send>),
+}
+
+/// A numbered playback with a fader and go/stop/flash buttons.
+///
+/// On presets and cue lists the fader scales the intensities, on effects the
+/// size or the speed depending on `fader_mode`.
+pub struct Executor {
+    pub name: String,
+    pub playback: Playback,
+    pub priority: u8,
+    pub fader_mode: FaderMode,
+    level: u8,
+    running: bool,
+    flash: bool,
+    /// Started by the flash button, so stopped again when it is released.
+    flash_started: bool,
+    activated: u64,
+    /// What the fixtures had before the executor first set them, restored
+    /// when it stops.
+    base: HashMap<(u8, Attribute), u16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutorStatus {
+    pub number: u32,
+    pub name: String,
+    /// "preset 3", "cue list" or "effect".
+    pub playback: String,
+    pub level: u8,
+    pub running: bool,
+    pub flash: bool,
+    /// Current cue of a cue list.
+    pub cue: Option<u32>,
+}
+
+impl Executor {
+    /// Stopped, with the fader at full.
+    pub fn new(playback: Playback) -> Executor {
+        Executor {
+            name: String::new(),
+            playback,
+            priority: merge::DEFAULT_PRIORITY,
+            fader_mode: FaderMode::default(),
+            level: 255,
+            running: false,
+            flash: false,
+            flash_started: false,
+            activated: 0,
+            base: HashMap::new(),
+        }
+    }
+
+    pub fn status(&self, number: u32) -> ExecutorStatus {
+        let (playback, cue) = match &self.playback {
+            Playback::Preset(preset) => (format!("preset {}", preset), None),
+            Playback::CueList(list) => ("cue list".to_string(), list.status().current),
+            Playback::Effect(_) => ("effect".to_string(), None),
+        };
+        ExecutorStatus {
+            number,
+            name: self.name.clone(),
+            playback,
+            level: self.level,
+            running: self.running,
+            flash: self.flash,
+            cue,
+        }
+    }
+
+    /// 0.0 to 1.0, full while flashing.
+    fn level(&self) -> f64 {
+        if self.flash {
+            1.0
+        } else {
+            self.level as f64 / 255.0
+        }
+    }
+
+    /// Returns false if a cue list has no next cue.
+    fn go(&mut self) -> bool {
+        if let Playback::CueList(list) = &mut self.playback
+            && !list.command(CueCommand::Go)
+        {
+            return false;
+        }
+        self.running = true;
+        self.activated = merge::activation();
+        true
+    }
+
+    /// Returns the values to restore.
+    fn stop(&mut self) -> HashMap<(u8, Attribute), u16> {
+        self.running = false;
+        self.flash_started = false;
+        if let Playback::CueList(list) = &mut self.playback {
+            list.reset();
+        }
+        std::mem::take(&mut self.base)
+    }
+
+    /// Keep `before` as the base of every attribute that doesn't have one.
+    fn remember_base(&mut self, before: Vec<PresetValue>) {
+        for value in before {
+            self.base
+                .entry((value.fixture_id, value.attribute))
+                .or_insert(value.value);
+        }
+    }
+
+    /// Advance the playback by `delta` and return what it sets, nothing when
+    /// stopped.
+    pub fn layer(&mut self, delta: Duration, show: &mut Show) -> Option<Layer> {
+        if !self.running {
+            return None;
+        }
+        let level = self.level();
+        let scale_intensities = |values: Vec<PresetValue>| -> Vec<PresetValue> {
+            values
+                .into_iter()
+                .map(|mut value| {
+                    if is_htp(&value.attribute) {
+                        value.value = (value.value as f64 * level).round() as u16;
+                    }
+                    value
+                })
+                .collect()
+        };
+
+        let keys = |values: &[PresetValue]| -> Vec<(u8, Attribute)> {
+            values
+                .iter()
+                .map(|value| (value.fixture_id, value.attribute.clone()))
+                .collect()
+        };
+
+        // Each with the values of the fixtures before this frame's merge
+        let (values, before) = match &mut self.playback {
+            Playback::Preset(number) => {
+                let values = show
+                    .presets
+                    .get(*number)
+                    .map(|preset| preset.values.clone())
+                    .unwrap_or_default();
+                let before = current_values(show, keys(&values));
+                (scale_intensities(values), before)
+            }
+            Playback::CueList(list) => {
+                list.tick(delta, show);
+                let values = list.output();
+                let before = current_values(show, keys(&values));
+                (scale_intensities(values), before)
+            }
+            Playback::Effect(effect) => {
+                // The effect writes into the fixtures, so read them first
+                let before = current_values(show, effect.driven(show));
+                match self.fader_mode {
+                    FaderMode::Size => {
+                        effect.set_size(level);
+                        effect.tick(delta, show);
+                    }
+                    FaderMode::Speed => {
+                        effect.set_size(1.0);
+                        effect.tick(delta.mul_f64(level), show);
+                    }
+                }
+                (current_values(show, effect.driven(show)), before)
+            }
+        };
+        self.remember_base(before);
+
+        Some(Layer {
+            priority: self.priority,
+            activated: self.activated,
+            values,
+        })
+    }
+}
+
+/// Run `command` on executor `number`. Returns false if there is no such
+/// executor or nothing to go to.
+pub fn command(show: &mut Show, number: u32, command: ExecutorCommand) -> bool {
+    let Some(executor) = show.executors.get_mut(&number) else {
+        return false;
+    };
+    let restore = match command {
+        ExecutorCommand::Go => return executor.go(),
+        ExecutorCommand::Stop => executor.stop(),
+        ExecutorCommand::Flash(true) => {
+            executor.flash = true;
+            if !executor.running {
+                executor.flash_started = executor.go();
+            }
+            return true;
+        }
+        ExecutorCommand::Flash(false) => {
+            executor.flash = false;
+            if !executor.flash_started {
+                return true;
+            }
+            executor.stop()
+        }
+        ExecutorCommand::Level(level) => {
+            executor.level = level;
+            return true;
+        }
+    };
+
+    // Other playbacks take the attributes over again on the next frame
+    for ((fixture_id, attribute), value) in restore {
+        if let Some(fixture) = show.get_fixture_by_id_mut(fixture_id) {
+            fixture.set_attribute(&attribute, value);
+        }
+    }
+    true
+}
+
+/// Status of every executor of `show`, by number.
+pub fn status(show: &Show) -> Vec<ExecutorStatus> {
+    show.executors
+        .iter()
+        .map(|(&number, executor)| executor.status(number))
+        .collect()
+}
+
+/// An executor as written in the show file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutorConfig {
+    pub number: u32,
+    #[serde(default)]
+    pub name: String,
+    #[serde(flatten)]
+    pub playback: PlaybackConfig,
+    #[serde(default)]
+    pub priority: u8,
+    #[serde(default)]
+    pub fader: FaderMode,
+}
+
+/// `"preset": 3`, `"cues": [...]`, `"effect": {...}` or
+/// `"position_effect": {...}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackConfig {
+    Preset(u32),
+    /// In any order, checked like the main cue list.
+    Cues(Vec<Cue>),
+    Effect(WaveformEffect),
+    PositionEffect(PositionEffect),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{
+        effect::{ColorSwapEffect, Speed, Waveform},
+        lib::{preset::Preset, selection::Selection},
+        patching::get_show,
+        render::render,
+    };
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    fn head(show: &Show, attribute: Attribute) -> u16 {
+        show.get_fixture_by_id(8)
+            .unwrap()
+            .get_attribute(&attribute)
+            .unwrap()
+    }
+
+    /// The demo show with head 8 dark at pan 0 and executor 1 holding a
+    /// preset of it at full, pan 3000.
+    fn preset_show() -> Show {
+        let mut show = get_show();
+        let fixture = show.get_fixture_by_id_mut(8).unwrap();
+        fixture.set_attribute(&Attribute::Dimmer, 0);
+        fixture.set_attribute(&Attribute::Pan, 0);
+        let values = [(Attribute::Dimmer, 65535), (Attribute::Pan, 3000)]
+            .into_iter()
+            .map(|(attribute, value)| PresetValue {
+                fixture_id: 8,
+                attribute,
+                value,
+            })
+            .collect();
+        show.presets
+            .store(
+                1,
+                Preset {
+                    name: String::new(),
+                    values,
+                },
+            )
+            .unwrap();
+        show.executors.insert(1, Executor::new(Playback::Preset(1)));
+        show
+    }
+
+    #[test]
+    fn fader_scales_intensity_and_stop_restores() {
+        let mut show = preset_show();
+        render(&mut show, FRAME, &[], 1, Instant::now());
+        assert_eq!(head(&show, Attribute::Dimmer), 0);
+
+        assert!(command(&mut show, 1, ExecutorCommand::Go));
+        render(&mut show, FRAME, &[], 2, Instant::now());
+        assert_eq!(head(&show, Attribute::Dimmer), 65535);
+        assert_eq!(head(&show, Attribute::Pan), 3000);
+
+        // Half the fader, half the intensity, pan stays
+        command(&mut show, 1, ExecutorCommand::Level(128));
+        render(&mut show, FRAME, &[], 3, Instant::now());
+        assert_eq!(head(&show, Attribute::Dimmer), 128 * 257);
+        assert_eq!(head(&show, Attribute::Pan), 3000);
+
+        command(&mut show, 1, ExecutorCommand::Stop);
+        render(&mut show, FRAME, &[], 4, Instant::now());
+        assert_eq!(head(&show, Attribute::Dimmer), 0);
+        assert_eq!(head(&show, Attribute::Pan), 0);
+        assert!(!show.executors[&1].status(1).running);
+        assert!(!command(&mut show, 2, ExecutorCommand::Go));
+    }
+
+    #[test]
+    fn flash_runs_at_full_while_held() {
+        let mut show = preset_show();
+        command(&mut show, 1, ExecutorCommand::Level(0));
+
+        command(&mut show, 1, ExecutorCommand::Flash(true));
+        render(&mut show, FRAME, &[], 1, Instant::now());
+        assert_eq!(head(&show, Attribute::Dimmer), 65535);
+
+        command(&mut show, 1, ExecutorCommand::Flash(false));
+        render(&mut show, FRAME, &[], 2, Instant::now());
+        assert_eq!(head(&show, Attribute::Dimmer), 0);
+        assert!(!show.executors[&1].status(1).running);
+
+        // Flashing a running executor leaves it running at its fader level
+        command(&mut show, 1, ExecutorCommand::Go);
+        command(&mut show, 1, ExecutorCommand::Flash(true));
+        command(&mut show, 1, ExecutorCommand::Flash(false));
+        render(&mut show, FRAME, &[], 3, Instant::now());
+        assert!(show.executors[&1].status(1).running);
+        assert_eq!(head(&show, Attribute::Pan), 3000);
+        assert_eq!(head(&show, Attribute::Dimmer), 0);
+    }
+
+    #[test]
+    fn fader_scales_effect_size_or_speed() {
+        let mut show = get_show();
+        let mut wave = WaveformEffect::new(
+            Attribute::Pan,
+            Selection::Fixtures(vec![8]),
+            Waveform::Saw,
+            Speed::Hz(1.0),
+        );
+        wave.base = 0.5;
+        show.executors
+            .insert(1, Executor::new(Playback::Effect(Box::new(wave.clone()))));
+        let mut speed = Executor::new(Playback::Effect(Box::new(wave)));
+        speed.fader_mode = FaderMode::Speed;
+        show.executors.insert(2, speed);
+
+        command(&mut show, 1, ExecutorCommand::Go);
+        command(&mut show, 1, ExecutorCommand::Level(0));
+        for tick in 0..5 {
+            render(&mut show, FRAME, &[], tick, Instant::now());
+            // No size left, the head stays on the base
+            assert_eq!(head(&show, Attribute::Pan), 32768);
+        }
+
+        command(&mut show, 1, ExecutorCommand::Stop);
+        command(&mut show, 2, ExecutorCommand::Go);
+        command(&mut show, 2, ExecutorCommand::Level(0));
+        render(&mut show, FRAME, &[], 5, Instant::now());
+        let frozen = head(&show, Attribute::Pan);
+        render(&mut show, FRAME, &[], 6, Instant::now());
+        assert_eq!(head(&show, Attribute::Pan), frozen);
+        command(&mut show, 2, ExecutorCommand::Level(255));
+        render(&mut show, FRAME, &[], 7, Instant::now());
+        assert_ne!(head(&show, Attribute::Pan), frozen);
+    }
+
+    #[test]
+    fn fader_dims_a_color_swap() {
+        let mut show = get_show();
+        let swap = ColorSwapEffect::new(60.0, "pars".into(), false, false);
+        show.executors
+            .insert(1, Executor::new(Playback::Effect(Box::new(swap))));
+        let cool_white = |show: &Show| {
+            show.get_fixture_by_id(1)
+                .unwrap()
+                .get_attribute(&Attribute::Custom("cool_white".to_string()))
+                .unwrap()
+                / 257
+        };
+
+        command(&mut show, 1, ExecutorCommand::Go);
+        render(&mut show, FRAME, &[], 1, Instant::now());
+        assert_eq!(cool_white(&show), 255);
+
+        command(&mut show, 1, ExecutorCommand::Level(128));
+        render(&mut show, FRAME, &[], 2, Instant::now());
+        assert!((127..=128).contains(&cool_white(&show)));
+
+        command(&mut show, 1, ExecutorCommand::Level(0));
+        render(&mut show, FRAME, &[], 3, Instant::now());
+        assert_eq!(cool_white(&show), 0);
+    }
+}
@@ -0,0 +1,553 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::lib::{fixture::Attribute, preset::PresetValue, show::Show};
+
+/// One step of a cue list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cue {
+    pub number: u32,
+    #[serde(default)]
+    pub name: String,
+    /// Only what changes in this cue, everything else tracks from the cues
+    /// before it.
+    pub values: Vec<PresetValue>,
+    /// Fade time of everything except dimmers going down.
+    #[serde(default)]
+    pub fade_in_ms: u64,
+    /// Fade time of dimmers going down.
+    #[serde(default)]
+    pub fade_out_ms: u64,
+    /// Wait between GO and the start of the fades.
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// Go to the next cue this long after this one has finished fading.
+    #[serde(default)]
+    pub follow_ms: Option<u64>,
+}
+
+impl Cue {
+    /// Time from GO until every fade is done.
+    fn duration(&self) -> Duration {
+        Duration::from_millis(self.delay_ms + self.fade_in_ms.max(self.fade_out_ms))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CueCommand {
+    /// Next cue, or resume when paused.
+    Go,
+    /// Previous cue, in its own times.
+    Back,
+    /// Freeze or resume the running fade and follow.
+    Pause,
+    /// The cue with the given number.
+    Goto(u32),
+}
+
+type Key = (u8, Attribute);
+
+/// A crossfade into `cue`, started by a command.
+#[derive(Debug, Clone)]
+struct Crossfade {
+    cue: usize,
+    /// Taken on the first tick, so fixtures reached by the cue for the first
+    /// time start from where they are.
+    from: Option<HashMap<Key, u16>>,
+    elapsed: Duration,
+    /// Intensities the list held that `cue` doesn't track, faded to zero
+    /// and then let go.
+    fading_out: Vec<Key>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CueStatus {
+    pub number: u32,
+    pub name: String,
+    pub values: usize,
+    pub fade_in_ms: u64,
+    pub fade_out_ms: u64,
+    pub delay_ms: u64,
+    pub follow_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CueListStatus {
+    pub cues: Vec<CueStatus>,
+    /// The cue that is fading in or was reached last.
+    pub current: Option<u32>,
+    /// What GO would start.
+    pub next: Option<u32>,
+    /// 0.0 to 1.0 of the running crossfade, including its delay.
+    pub progress: Option<f64>,
+    pub paused: bool,
+}
+
+/// Cues in order of their numbers, played back with tracking.
+///
+/// The list keeps its own output, which the render loop merges with the
+/// other playbacks every frame once a cue has been started.
+#[derive(Debug, Clone, Default)]
+pub struct CueList {
+    pub priority: u8,
+    /// When the current cue was started, for LTP.
+    activated: u64,
+    cues: Vec<Cue>,
+    current: Option<usize>,
+    fade: Option<Crossfade>,
+    paused: bool,
+    /// Time since the current cue finished fading, for its follow.
+    since_complete: Duration,
+    output: HashMap<Key, u16>,
+}
+
+impl CueList {
+    /// `cues` must be sorted by number.
+    pub fn new(cues: Vec<Cue>) -> CueList {
+        CueList {
+            cues,
+            ..CueList::default()
+        }
+    }
+
+    /// Every value in effect at cue `index`, tracked through the cues before.
+    fn tracked(&self, index: usize) -> HashMap<Key, u16> {
+        let mut state = HashMap::new();
+        for cue in &self.cues[..=index] {
+            for value in &cue.values {
+                state.insert((value.fixture_id, value.attribute.clone()), value.value);
+            }
+        }
+        state
+    }
+
+    /// Record `values` into cue `number`. An existing cue is updated, a new
+    /// one only keeps what differs from the cues before it. Returns how many
+    /// values the cue holds.
+    pub fn record(&mut self, number: u32, values: &[PresetValue]) -> usize {
+        let index = self.cues.partition_point(|cue| cue.number < number);
+        if self.cues.get(index).is_none_or(|cue| cue.number != number) {
+            let previous = match index {
+                0 => HashMap::new(),
+                _ => self.tracked(index - 1),
+            };
+            let values = values
+                .iter()
+                .filter(|v| previous.get(&(v.fixture_id, v.attribute.clone())) != Some(&v.value))
+                .cloned()
+                .collect();
+            self.cues.insert(
+                index,
+                Cue {
+                    number,
+                    name: String::new(),
+                    values,
+                    fade_in_ms: 0,
+                    fade_out_ms: 0,
+                    delay_ms: 0,
+                    follow_ms: None,
+                },
+            );
+            // Keep pointing at the same cues
+            for position in self
+                .current
+                .iter_mut()
+                .chain(self.fade.as_mut().map(|fade| &mut fade.cue))
+            {
+                if *position >= index {
+                    *position += 1;
+                }
+            }
+            return self.cues[index].values.len();
+        }
+
+        let cue = &mut self.cues[index];
+        for value in values {
+            match cue
+                .values
+                .iter_mut()
+                .find(|v| v.fixture_id == value.fixture_id && v.attribute == value.attribute)
+            {
+                Some(existing) => existing.value = value.value,
+                None => cue.values.push(value.clone()),
+            }
+        }
+        cue.values.len()
+    }
+
+    fn start(&mut self, index: usize) {
+        // What only later cues set isn't part of this cue, e.g. after BACK:
+        // intensities fade out with it, everything else is let go at once
+        let target = self.tracked(index);
+        self.output
+            .retain(|key, _| target.contains_key(key) || key.1 == Attribute::Dimmer);
+        let fading_out = self
+            .output
+            .keys()
+            .filter(|key| !target.contains_key(key))
+            .cloned()
+            .collect();
+
+        self.current = Some(index);
+        self.activated = crate::merge::activation();
+        self.fade = Some(Crossfade {
+            cue: index,
+            from: None,
+            elapsed: Duration::ZERO,
+            fading_out,
+        });
+        self.paused = false;
+    }
+
+    /// Run `command`. Returns false if there is no cue to go to.
+    pub fn command(&mut self, command: CueCommand) -> bool {
+        let target = match command {
+            CueCommand::Go if self.paused => {
+                self.paused = false;
+                return true;
+            }
+            CueCommand::Go => self.current.map_or(0, |i| i + 1),
+            CueCommand::Back => match self.current {
+                Some(i) if i > 0 => i - 1,
+                _ => return false,
+            },
+            CueCommand::Pause => {
+                self.paused = !self.paused;
+                return true;
+            }
+            CueCommand::Goto(number) => match self.cues.iter().position(|c| c.number == number) {
+                Some(i) => i,
+                None => return false,
+            },
+        };
+        if target >= self.cues.len() {
+            return false;
+        }
+        self.start(target);
+        true
+    }
+
+    /// Back to before the first cue, with nothing set.
+    pub fn reset(&mut self) {
+        self.current = None;
+        self.fade = None;
+        self.paused = false;
+        self.since_complete = Duration::ZERO;
+        self.output.clear();
+    }
+
+    pub fn activated(&self) -> u64 {
+        self.activated
+    }
+
+    /// What the list sets, nothing before the first cue is started.
+    pub fn output(&self) -> Vec<PresetValue> {
+        self.output
+            .iter()
+            .map(|((fixture_id, attribute), value)| PresetValue {
+                fixture_id: *fixture_id,
+                attribute: attribute.clone(),
+                value: *value,
+            })
+            .collect()
+    }
+
+    /// Advance the running crossfade or follow by `delta`. Fixtures a cue
+    /// reaches for the first time fade from their values in `show`.
+    pub fn tick(&mut self, delta: Duration, show: &Show) {
+        if self.paused {
+            return;
+        }
+
+        let Some(index) = self.fade.as_ref().map(|fade| fade.cue) else {
+            let follow = self
+                .current
+                .and_then(|i| self.cues[i].follow_ms)
+                .map(Duration::from_millis);
+            if let Some(follow) = follow {
+                self.since_complete += delta;
+                if self.since_complete >= follow {
+                    self.command(CueCommand::Go);
+                }
+            }
+            return;
+        };
+
+        let mut target = self.tracked(index);
+        let cue = &self.cues[index];
+        let Some(fade) = &mut self.fade else {
+            return;
+        };
+        target.extend(fade.fading_out.iter().map(|key| (key.clone(), 0)));
+        let output = &self.output;
+        let from = fade.from.get_or_insert_with(|| {
+            target
+                .keys()
+                .map(|key| {
+                    let current = output.get(key).copied().or_else(|| {
+                        show.get_fixture_by_id(key.0)
+                            .and_then(|f| f.get_attribute(&key.1))
+                    });
+                    (key.clone(), current.unwrap_or(target[key]))
+                })
+                .collect()
+        });
+
+        fade.elapsed += delta;
+        let running = fade
+            .elapsed
+            .saturating_sub(Duration::from_millis(cue.delay_ms));
+        for (key, &to) in &target {
+            let start = from[key];
+            let time = if key.1 == Attribute::Dimmer && to < start {
+                cue.fade_out_ms
+            } else {
+                cue.fade_in_ms
+            };
+            let progress = if fade.elapsed < Duration::from_millis(cue.delay_ms) {
+                0.0
+            } else if time == 0 {
+                1.0
+            } else {
+                (running.as_secs_f64() * 1000.0 / time as f64).min(1.0)
+            };
+            let value = match key.1 {
+                // Wheels can't fade, they change as soon as the fade starts
+                Attribute::ColorWheel | Attribute::Gobo if progress > 0.0 => to,
+                Attribute::ColorWheel | Attribute::Gobo => start,
+                _ => (start as f64 + (to as f64 - start as f64) * progress).round() as u16,
+            };
+            self.output.insert(key.clone(), value);
+        }
+
+        if fade.elapsed >= cue.duration() {
+            for key in &fade.fading_out {
+                self.output.remove(key);
+            }
+            self.fade = None;
+            self.since_complete = Duration::ZERO;
+        }
+    }
+
+    pub fn status(&self) -> CueListStatus {
+        let number = |index: usize| self.cues.get(index).map(|c| c.number);
+        CueListStatus {
+            cues: self
+                .cues
+                .iter()
+                .map(|cue| CueStatus {
+                    number: cue.number,
+                    name: cue.name.clone(),
+                    values: cue.values.len(),
+                    fade_in_ms: cue.fade_in_ms,
+                    fade_out_ms: cue.fade_out_ms,
+                    delay_ms: cue.delay_ms,
+                    follow_ms: cue.follow_ms,
+                })
+                .collect(),
+            current: self.current.and_then(number),
+            next: number(self.current.map_or(0, |i| i + 1)),
+            progress: self.fade.as_ref().map(|fade| {
+                let total = self.cues[fade.cue].duration();
+                if total.is_zero() {
+                    1.0
+                } else {
+                    (fade.elapsed.as_secs_f64() / total.as_secs_f64()).min(1.0)
+                }
+            }),
+            paused: self.paused,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{patching::get_show, render::render};
+
+    fn cue(number: u32, values: &[(Attribute, u16)]) -> Cue {
+        Cue {
+            number,
+            name: String::new(),
+            values: values
+                .iter()
+                .map(|(attribute, value)| PresetValue {
+                    fixture_id: 8,
+                    attribute: attribute.clone(),
+                    value: *value,
+                })
+                .collect(),
+            fade_in_ms: 0,
+            fade_out_ms: 0,
+            delay_ms: 0,
+            follow_ms: None,
+        }
+    }
+
+    fn step(show: &mut Show, ms: u64) {
+        render(show, Duration::from_millis(ms), &[], 0, Instant::now());
+    }
+
+    fn head(show: &Show, attribute: Attribute) -> u16 {
+        show.get_fixture_by_id(8)
+            .unwrap()
+            .get_attribute(&attribute)
+            .unwrap()
+    }
+
+    #[test]
+    fn cues_track_and_fade_with_their_times() {
+        let mut show = get_show();
+        let fixture = show.get_fixture_by_id_mut(8).unwrap();
+        fixture.set_attribute(&Attribute::Dimmer, 0);
+        fixture.set_attribute(&Attribute::Pan, 0);
+        fixture.set_attribute(&Attribute::Tilt, 0);
+
+        let mut first = cue(1, &[(Attribute::Pan, 20000), (Attribute::Dimmer, 65535)]);
+        first.fade_in_ms = 1000;
+        let mut second = cue(2, &[(Attribute::Tilt, 30000)]);
+        second.fade_in_ms = 1000;
+        second.delay_ms = 500;
+        let mut third = cue(3, &[(Attribute::Dimmer, 0)]);
+        third.fade_out_ms = 2000;
+        show.cues = CueList::new(vec![first, second, third]);
+
+        assert!(show.cues.command(CueCommand::Go));
+        step(&mut show, 500);
+        assert_eq!(head(&show, Attribute::Pan), 10000);
+        step(&mut show, 500);
+        assert_eq!(head(&show, Attribute::Pan), 20000);
+        assert_eq!(head(&show, Attribute::Dimmer), 65535);
+        assert_eq!(show.cues.status().progress, None);
+
+        // Nothing moves during the delay, pan tracks from cue 1
+        show.cues.command(CueCommand::Go);
+        step(&mut show, 500);
+        assert_eq!(head(&show, Attribute::Tilt), 0);
+        step(&mut show, 500);
+        assert_eq!(head(&show, Attribute::Tilt), 15000);
+        step(&mut show, 500);
+        assert_eq!(head(&show, Attribute::Tilt), 30000);
+        assert_eq!(head(&show, Attribute::Pan), 20000);
+
+        // The dimmer goes down in the out time even though the in time is 0
+        show.cues.command(CueCommand::Go);
+        step(&mut show, 1000);
+        assert_eq!(show.cues.status().progress, Some(0.5));
+        let half = head(&show, Attribute::Dimmer);
+        assert!((32000..33500).contains(&half), "{}", half);
+        step(&mut show, 1000);
+        assert_eq!(head(&show, Attribute::Dimmer), 0);
+        assert_eq!(head(&show, Attribute::Tilt), 30000);
+        assert!(!show.cues.command(CueCommand::Go));
+    }
+
+    #[test]
+    fn follow_pause_back_and_goto() {
+        let mut show = get_show();
+        let mut first = cue(1, &[(Attribute::Pan, 10000)]);
+        first.follow_ms = Some(1000);
+        let mut second = cue(2, &[(Attribute::Pan, 20000)]);
+        second.fade_in_ms = 1000;
+        let third = cue(5, &[(Attribute::Pan, 30000)]);
+        show.cues = CueList::new(vec![first, second, third]);
+        assert_eq!(show.cues.status().next, Some(1));
+
+        show.cues.command(CueCommand::Go);
+        step(&mut show, 250);
+        assert_eq!(head(&show, Attribute::Pan), 10000);
+        for _ in 0..4 {
+            step(&mut show, 250);
+        }
+        let status = show.cues.status();
+        assert_eq!((status.current, status.next), (Some(2), Some(5)));
+
+        // Paused fades hold until GO resumes them
+        show.cues.command(CueCommand::Pause);
+        step(&mut show, 500);
+        assert_eq!(head(&show, Attribute::Pan), 10000);
+        assert!(show.cues.status().paused);
+        show.cues.command(CueCommand::Go);
+        step(&mut show, 500);
+        assert_eq!(head(&show, Attribute::Pan), 15000);
+        assert_eq!(show.cues.status().current, Some(2));
+
+        show.cues.command(CueCommand::Back);
+        step(&mut show, 250);
+        assert_eq!(head(&show, Attribute::Pan), 10000);
+
+        assert!(show.cues.command(CueCommand::Goto(5)));
+        step(&mut show, 250);
+        assert_eq!(head(&show, Attribute::Pan), 30000);
+        assert!(!show.cues.command(CueCommand::Goto(3)));
+    }
+
+    #[test]
+    fn back_and_goto_drop_what_only_later_cues_set() {
+        let show = get_show();
+        let mut first = cue(1, &[(Attribute::Pan, 10000)]);
+        first.fade_out_ms = 1000;
+        let second = cue(2, &[(Attribute::Tilt, 30000), (Attribute::Dimmer, 65535)]);
+        let third = cue(3, &[(Attribute::Pan, 20000)]);
+        let mut list = CueList::new(vec![first, second, third]);
+        let output = |list: &CueList| {
+            let mut output: Vec<(Attribute, u16)> = list
+                .output()
+                .into_iter()
+                .map(|v| (v.attribute, v.value))
+                .collect();
+            output.sort_by_key(|(attribute, _)| format!("{:?}", attribute));
+            output
+        };
+
+        list.command(CueCommand::Go);
+        list.tick(Duration::ZERO, &show);
+        list.command(CueCommand::Go);
+        list.tick(Duration::ZERO, &show);
+
+        // Tilt is let go at once, the dimmer fades out in cue 1's out time
+        list.command(CueCommand::Back);
+        list.tick(Duration::from_millis(500), &show);
+        assert_eq!(
+            output(&list),
+            [(Attribute::Dimmer, 32768), (Attribute::Pan, 10000)]
+        );
+        list.tick(Duration::from_millis(500), &show);
+        assert_eq!(output(&list), [(Attribute::Pan, 10000)]);
+
+        list.command(CueCommand::Goto(3));
+        list.tick(Duration::ZERO, &show);
+        assert_eq!(
+            output(&list),
+            [
+                (Attribute::Dimmer, 65535),
+                (Attribute::Pan, 20000),
+                (Attribute::Tilt, 30000)
+            ]
+        );
+        list.command(CueCommand::Goto(1));
+        list.tick(Duration::from_secs(1), &show);
+        assert_eq!(output(&list), [(Attribute::Pan, 10000)]);
+    }
+
+    #[test]
+    fn recorded_cues_only_keep_changes() {
+        let mut list = CueList::new(vec![
+            cue(1, &[(Attribute::Pan, 100), (Attribute::Tilt, 200)]),
+            cue(3, &[(Attribute::Tilt, 300)]),
+        ]);
+        list.command(CueCommand::Goto(3));
+        let values = cue(0, &[(Attribute::Pan, 100), (Attribute::Tilt, 250)]).values;
+
+        assert_eq!(list.record(2, &values), 1);
+        assert_eq!(list.cues[1].values, values[1..]);
+        // Still on cue 3, now the third in the list
+        assert_eq!(list.status().current, Some(3));
+
+        assert_eq!(list.record(1, &cue(0, &[(Attribute::Dimmer, 0)]).values), 3);
+    }
+}
@@ -1,17 +1,29 @@
-use std::vec;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Fixture {
     pub id: u8,
     pub name: String,
+    /// Logical DMX universe the fixture is patched to (1-based).
+    #[serde(default = "default_universe")]
+    pub universe: u16,
     pub dmx_address: u16,
     pub components: Vec<FixtureComponent>,
 }
 
+fn default_universe() -> u16 {
+    1
+}
+
+/// Pan and tilt range in degrees of fixtures that don't state theirs.
+pub const DEFAULT_PAN_RANGE: f64 = 540.0;
+pub const DEFAULT_TILT_RANGE: f64 = 270.0;
+
 impl Fixture {
     pub fn new(id: u8, dmx_address: u16, name: String) -> Fixture {
         Fixture {
             id,
+            universe: default_universe(),
             dmx_address,
             components: Vec::new(),
             name,
@@ -20,16 +32,109 @@ impl Fixture {
     pub fn add_component(&mut self, component: FixtureComponent) {
         self.components.push(component);
     }
+    /// Number of DMX channels the fixture occupies.
+    pub fn footprint(&self) -> u16 {
+        self.components
+            .iter()
+            .map(|c| c.channel_count() as u16)
+            .sum()
+    }
     pub fn get_dmx_values(&self) -> Vec<u8> {
         let mut dmx_values = Vec::new();
         for component in &self.components {
-            dmx_values.append(&mut component.get_dmx_values());
+            match component {
+                // Fine channels carry the low byte of another component's value
+                FixtureComponent::Fine(fine) => dmx_values.push(self.fine_value(&fine.attribute)),
+                _ => dmx_values.append(&mut component.get_dmx_values()),
+            }
+        }
+        dmx_values
+    }
+
+    /// Every attribute the fixture has, in channel order.
+    pub fn attributes(&self) -> Vec<Attribute> {
+        let mut attributes: Vec<Attribute> = Vec::new();
+        for attribute in self.components.iter().flat_map(|c| c.attributes()) {
+            if !attributes.contains(&attribute) {
+                attributes.push(attribute);
+            }
+        }
+        attributes
+    }
+
+    /// Value of `attribute` as 16 bit, the dimmer gives its local level.
+    pub fn get_attribute(&self, attribute: &Attribute) -> Option<u16> {
+        self.components
+            .iter()
+            .find_map(|component| component.value_16bit(attribute))
+    }
+
+    /// Set `attribute` on every component that has it, from a 16 bit value.
+    /// Returns false if the fixture has no such attribute.
+    pub fn set_attribute(&mut self, attribute: &Attribute, value: u16) -> bool {
+        let mut found = false;
+        for component in self.components.iter_mut() {
+            found |= component.set_value_16bit(attribute, value);
         }
-        return dmx_values;
+        found
+    }
+
+    /// Pan and tilt range in degrees, from the fixture's position components.
+    pub fn pan_tilt_range(&self) -> (f64, f64) {
+        let mut pan_range = None;
+        let mut tilt_range = None;
+        for component in &self.components {
+            match component {
+                FixtureComponent::Position(p) => {
+                    pan_range = pan_range.or(p.pan_range);
+                    tilt_range = tilt_range.or(p.tilt_range);
+                }
+                FixtureComponent::Pan(a) => pan_range = pan_range.or(a.range),
+                FixtureComponent::Tilt(a) => tilt_range = tilt_range.or(a.range),
+                _ => {}
+            }
+        }
+        (
+            pan_range.unwrap_or(DEFAULT_PAN_RANGE),
+            tilt_range.unwrap_or(DEFAULT_TILT_RANGE),
+        )
+    }
+
+    /// Low byte of what the coarse channel of `attribute` sends. Like its
+    /// coarse channel the dimmer sends its intensity, not its local level.
+    fn fine_value(&self, attribute: &Attribute) -> u8 {
+        self.components
+            .iter()
+            .find_map(|component| match component {
+                FixtureComponent::Dimmer(d) if *attribute == Attribute::Dimmer => {
+                    Some(d.intensity as u16 * 257)
+                }
+                _ => component.value_16bit(attribute),
+            })
+            .map(|value| (value & 0xFF) as u8)
+            .unwrap_or(0)
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+/// A controllable property of a fixture, independent of the channel layout.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Attribute {
+    Dimmer,
+    Pan,
+    Tilt,
+    Red,
+    Green,
+    Blue,
+    ColorWheel,
+    Gobo,
+    Focus,
+    /// A `CustomValue` component with this name.
+    Custom(String),
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum FixtureComponent {
     Color(Color),
     Dimmer(Dimmer),
@@ -41,9 +146,90 @@ pub enum FixtureComponent {
     Zero,
     Gobo(GoboWheel),
     Focus(Focus),
+    /// Pan coarse channel, for layouts that don't match `Position`.
+    Pan(Axis),
+    /// Tilt coarse channel, for layouts that don't match `Position`.
+    Tilt(Axis),
+    Fine(FineChannel),
 }
 
 impl FixtureComponent {
+    /// Number of DMX channels the component occupies.
+    pub fn channel_count(&self) -> usize {
+        match self {
+            FixtureComponent::Color(_) => 3,
+            FixtureComponent::Position(_) => 4,
+            _ => 1,
+        }
+    }
+
+    /// Attributes the component controls.
+    fn attributes(&self) -> Vec<Attribute> {
+        match self {
+            FixtureComponent::Dimmer(_) => vec![Attribute::Dimmer],
+            FixtureComponent::Position(_) => vec![Attribute::Pan, Attribute::Tilt],
+            FixtureComponent::Pan(_) => vec![Attribute::Pan],
+            FixtureComponent::Tilt(_) => vec![Attribute::Tilt],
+            FixtureComponent::Color(_) => vec![Attribute::Red, Attribute::Green, Attribute::Blue],
+            FixtureComponent::ColorWheel(_) => vec![Attribute::ColorWheel],
+            FixtureComponent::Gobo(_) => vec![Attribute::Gobo],
+            FixtureComponent::Focus(_) => vec![Attribute::Focus],
+            FixtureComponent::CustomValue(c) => vec![Attribute::Custom(c.name.clone())],
+            FixtureComponent::Placeholder
+            | FixtureComponent::Max
+            | FixtureComponent::Zero
+            | FixtureComponent::Fine(_) => Vec::new(),
+        }
+    }
+
+    /// The component's value for `attribute` scaled to 16 bit, 8 bit values
+    /// are repeated in the low byte so a fine channel follows its coarse one.
+    /// The dimmer gives its local level.
+    fn value_16bit(&self, attribute: &Attribute) -> Option<u16> {
+        let value_8bit = |value: u8| Some(value as u16 * 257);
+        match (self, attribute) {
+            (FixtureComponent::Dimmer(d), Attribute::Dimmer) => value_8bit(d.local),
+            (FixtureComponent::Position(p), Attribute::Pan) => Some(p.pan),
+            (FixtureComponent::Position(p), Attribute::Tilt) => Some(p.tilt),
+            (FixtureComponent::Pan(a), Attribute::Pan) => Some(a.value),
+            (FixtureComponent::Tilt(a), Attribute::Tilt) => Some(a.value),
+            (FixtureComponent::Color(c), Attribute::Red) => value_8bit(c.r),
+            (FixtureComponent::Color(c), Attribute::Green) => value_8bit(c.g),
+            (FixtureComponent::Color(c), Attribute::Blue) => value_8bit(c.b),
+            (FixtureComponent::ColorWheel(c), Attribute::ColorWheel) => value_8bit(c.index),
+            (FixtureComponent::Gobo(g), Attribute::Gobo) => value_8bit(g.index),
+            (FixtureComponent::Focus(f), Attribute::Focus) => value_8bit(f.value),
+            (FixtureComponent::CustomValue(c), Attribute::Custom(name)) if &c.name == name => {
+                value_8bit(c.value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Counterpart of `value_16bit`, 8 bit values take the high byte. The
+    /// dimmer sets its local level, the global dimmer still applies.
+    fn set_value_16bit(&mut self, attribute: &Attribute, value: u16) -> bool {
+        let value_8bit = (value / 257) as u8;
+        match (self, attribute) {
+            (FixtureComponent::Dimmer(d), Attribute::Dimmer) => d.local = value_8bit,
+            (FixtureComponent::Position(p), Attribute::Pan) => p.pan = value,
+            (FixtureComponent::Position(p), Attribute::Tilt) => p.tilt = value,
+            (FixtureComponent::Pan(a), Attribute::Pan) => a.value = value,
+            (FixtureComponent::Tilt(a), Attribute::Tilt) => a.value = value,
+            (FixtureComponent::Color(c), Attribute::Red) => c.r = value_8bit,
+            (FixtureComponent::Color(c), Attribute::Green) => c.g = value_8bit,
+            (FixtureComponent::Color(c), Attribute::Blue) => c.b = value_8bit,
+            (FixtureComponent::ColorWheel(c), Attribute::ColorWheel) => c.index = value_8bit,
+            (FixtureComponent::Gobo(g), Attribute::Gobo) => g.index = value_8bit,
+            (FixtureComponent::Focus(f), Attribute::Focus) => f.value = value_8bit,
+            (FixtureComponent::CustomValue(c), Attribute::Custom(name)) if &c.name == name => {
+                c.value = value_8bit
+            }
+            _ => return false,
+        }
+        true
+    }
+
     fn get_dmx_values(&self) -> Vec<u8> {
         match self {
             FixtureComponent::Color(c) => {
@@ -80,18 +266,27 @@ impl FixtureComponent {
             FixtureComponent::Focus(f) => {
                 vec![f.value]
             }
+            FixtureComponent::Pan(a) | FixtureComponent::Tilt(a) => {
+                vec![(a.value >> 8) as u8]
+            }
+            FixtureComponent::Fine(_) => {
+                // resolved by Fixture::get_dmx_values
+                vec![0]
+            }
         }
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Dimmer {
     /// The user-set local dimmer (0-255). This is the fixture's own dimmer value
     /// before global scaling is applied.
@@ -100,29 +295,87 @@ pub struct Dimmer {
     pub intensity: u8,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Position {
     pub pan: u16,
     pub tilt: u16,
+    /// Degrees covered by the full pan range, `DEFAULT_PAN_RANGE` if unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pan_range: Option<f64>,
+    /// Degrees covered by the full tilt range, `DEFAULT_TILT_RANGE` if unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tilt_range: Option<f64>,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ColorWheel {
     pub index: u8,
+    pub slots: Vec<WheelSlot>,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CustomValue {
     pub name: String,
     pub value: u8,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GoboWheel {
     pub index: u8,
+    pub slots: Vec<WheelSlot>,
+}
+
+/// A named position on a color or gobo wheel.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WheelSlot {
+    pub name: String,
+    /// DMX value that selects the slot.
+    pub dmx_value: u8,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Focus {
     pub value: u8,
 }
+
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Axis {
+    pub value: u16,
+    /// Degrees covered by the full range of the axis.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct FineChannel {
+    /// The attribute whose low byte this channel carries.
+    pub attribute: Attribute,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dimmer_fine_channel_follows_the_scaled_coarse_channel() {
+        let mut fixture = Fixture::new(1, 1, "Dimmer 16 bit".to_string());
+        fixture.add_component(FixtureComponent::Dimmer(Dimmer {
+            local: 200,
+            intensity: 100,
+        }));
+        fixture.add_component(FixtureComponent::Fine(FineChannel {
+            attribute: Attribute::Dimmer,
+        }));
+
+        // The global dimmer has taken the intensity down from the local level
+        assert_eq!(fixture.get_dmx_values(), [100, 100]);
+        assert_eq!(fixture.get_attribute(&Attribute::Dimmer), Some(200 * 257));
+    }
+}
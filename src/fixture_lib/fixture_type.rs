@@ -0,0 +1,251 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::lib::fixture::{Fixture, FixtureComponent};
+
+/// A reusable fixture definition, e.g. "Brand/Model" with its DMX modes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureType {
+    pub manufacturer: String,
+    pub model: String,
+    pub modes: Vec<FixtureMode>,
+}
+
+/// One DMX personality of a fixture type. The components are listed in
+/// channel order and carry their default values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureMode {
+    pub name: String,
+    pub channels: Vec<FixtureComponent>,
+}
+
+impl FixtureMode {
+    /// Number of DMX channels this mode occupies.
+    pub fn footprint(&self) -> u16 {
+        self.channels.iter().map(|c| c.channel_count() as u16).sum()
+    }
+}
+
+impl FixtureType {
+    /// Library key, "Manufacturer/Model".
+    pub fn name(&self) -> String {
+        format!("{}/{}", self.manufacturer, self.model)
+    }
+
+    pub fn mode(&self, name: &str) -> Option<&FixtureMode> {
+        self.modes.iter().find(|m| m.name == name)
+    }
+
+    /// Create a fixture in the given mode at `dmx_address`.
+    pub fn instantiate(
+        &self,
+        mode: &str,
+        id: u8,
+        universe: u16,
+        dmx_address: u16,
+        name: String,
+    ) -> Result<Fixture, LibraryError> {
+        let mode = self.mode(mode).ok_or_else(|| LibraryError::UnknownMode {
+            fixture_type: self.name(),
+            mode: mode.to_string(),
+        })?;
+
+        let mut fixture = Fixture::new(id, dmx_address, name);
+        fixture.universe = universe;
+        for component in &mode.channels {
+            fixture.add_component(component.clone());
+        }
+        Ok(fixture)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LibraryError {
+    UnknownType(String),
+    UnknownMode { fixture_type: String, mode: String },
+    IdOutOfRange(u16),
+}
+
+impl fmt::Display for LibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LibraryError::UnknownType(name) => write!(f, "unknown fixture type '{}'", name),
+            LibraryError::UnknownMode { fixture_type, mode } => {
+                write!(f, "fixture type '{}' has no mode '{}'", fixture_type, mode)
+            }
+            LibraryError::IdOutOfRange(id) => write!(f, "fixture id {} is out of range", id),
+        }
+    }
+}
+
+impl std::error::Error for LibraryError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct FixtureLibrary {
+    pub types: Vec<FixtureType>,
+}
+
+impl FixtureLibrary {
+    pub fn new() -> FixtureLibrary {
+        FixtureLibrary { types: Vec::new() }
+    }
+
+    /// Add a type, replacing any existing type with the same name.
+    pub fn add_type(&mut self, fixture_type: FixtureType) {
+        let name = fixture_type.name();
+        self.types.retain(|t| t.name() != name);
+        self.types.push(fixture_type);
+    }
+
+    /// Look a type up by "Manufacturer/Model".
+    pub fn get(&self, name: &str) -> Option<&FixtureType> {
+        self.types.iter().find(|t| t.name() == name)
+    }
+
+    /// Patch `count` fixtures of one type back to back, starting at
+    /// `dmx_address` with ids counting up from `first_id`.
+    pub fn instantiate_many(
+        &self,
+        type_name: &str,
+        mode: &str,
+        count: u16,
+        first_id: u8,
+        universe: u16,
+        dmx_address: u16,
+    ) -> Result<Vec<Fixture>, LibraryError> {
+        let fixture_type = self
+            .get(type_name)
+            .ok_or_else(|| LibraryError::UnknownType(type_name.to_string()))?;
+        let footprint = fixture_type
+            .mode(mode)
+            .ok_or_else(|| LibraryError::UnknownMode {
+                fixture_type: type_name.to_string(),
+                mode: mode.to_string(),
+            })?
+            .footprint();
+
+        (0..count)
+            .map(|i| {
+                let id = u8::try_from(first_id as u16 + i)
+                    .map_err(|_| LibraryError::IdOutOfRange(first_id as u16 + i))?;
+                fixture_type.instantiate(
+                    mode,
+                    id,
+                    universe,
+                    dmx_address.saturating_add(i.saturating_mul(footprint)),
+                    format!("{} {}", fixture_type.model, i + 1),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::fixture::{Color, Dimmer, Position};
+
+    fn spot() -> FixtureType {
+        FixtureType {
+            manufacturer: "Acme".to_string(),
+            model: "Spot".to_string(),
+            modes: vec![
+                FixtureMode {
+                    name: "basic".to_string(),
+                    channels: vec![FixtureComponent::Dimmer(Dimmer::default())],
+                },
+                FixtureMode {
+                    name: "extended".to_string(),
+                    channels: vec![
+                        FixtureComponent::Position(Position::default()),
+                        FixtureComponent::Dimmer(Dimmer::default()),
+                        FixtureComponent::Color(Color::default()),
+                    ],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn library_looks_types_and_modes_up() {
+        let mut library = FixtureLibrary::new();
+        library.add_type(spot());
+        let mut renamed = spot();
+        renamed.modes.truncate(1);
+        library.add_type(renamed);
+
+        // Adding a type under the same name replaces it
+        assert_eq!(library.types.len(), 1);
+        let found = library.get("Acme/Spot").unwrap();
+        assert!(found.mode("basic").is_some());
+        assert!(found.mode("extended").is_none());
+        assert!(library.get("Acme/Wash").is_none());
+
+        library.add_type(spot());
+        let extended = library.get("Acme/Spot").unwrap().mode("extended").unwrap();
+        assert_eq!(extended.footprint(), 8);
+    }
+
+    #[test]
+    fn instantiate_many_patches_back_to_back() {
+        let mut library = FixtureLibrary::new();
+        library.add_type(spot());
+
+        let fixtures = library
+            .instantiate_many("Acme/Spot", "extended", 3, 10, 2, 101)
+            .unwrap();
+        let patch: Vec<(u8, u16, u16, &str)> = fixtures
+            .iter()
+            .map(|f| (f.id, f.universe, f.dmx_address, f.name.as_str()))
+            .collect();
+        assert_eq!(
+            patch,
+            [
+                (10, 2, 101, "Spot 1"),
+                (11, 2, 109, "Spot 2"),
+                (12, 2, 117, "Spot 3"),
+            ]
+        );
+        assert_eq!(fixtures[0].components, spot().modes[1].channels);
+    }
+
+    #[test]
+    fn library_errors() {
+        let mut library = FixtureLibrary::new();
+        library.add_type(spot());
+
+        let unknown_type = library
+            .instantiate_many("Acme/Wash", "basic", 1, 1, 1, 1)
+            .unwrap_err();
+        assert_eq!(
+            unknown_type,
+            LibraryError::UnknownType("Acme/Wash".to_string())
+        );
+        assert_eq!(unknown_type.to_string(), "unknown fixture type 'Acme/Wash'");
+
+        let unknown_mode = library
+            .instantiate_many("Acme/Spot", "16bit", 1, 1, 1, 1)
+            .unwrap_err();
+        assert_eq!(
+            unknown_mode,
+            LibraryError::UnknownMode {
+                fixture_type: "Acme/Spot".to_string(),
+                mode: "16bit".to_string(),
+            }
+        );
+        assert_eq!(
+            spot()
+                .instantiate("16bit", 1, 1, 1, "Spot".to_string())
+                .unwrap_err(),
+            unknown_mode
+        );
+
+        assert_eq!(
+            library
+                .instantiate_many("Acme/Spot", "basic", 3, 254, 1, 1)
+                .unwrap_err(),
+            LibraryError::IdOutOfRange(256)
+        );
+    }
+}
@@ -0,0 +1,477 @@
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// DMX received from another console for one universe.
+#[derive(Debug, Clone)]
+pub struct InputSource {
+    /// Identifies the sender across packets, e.g. "sacn:<cid>".
+    pub key: String,
+    /// Shown in the UI, e.g. "Guest Desk (192.168.1.20)".
+    pub label: String,
+    pub priority: u8,
+    pub frame: [u8; 512],
+    pub last_update: Instant,
+    /// The source is ignored once it has not sent for this long.
+    pub timeout: Duration,
+    /// When each channel last changed, for LTP merging.
+    changed: [Instant; 512],
+}
+
+impl InputSource {
+    pub fn new(
+        key: String,
+        label: String,
+        priority: u8,
+        frame: [u8; 512],
+        timeout: Duration,
+    ) -> InputSource {
+        let now = Instant::now();
+        InputSource {
+            key,
+            label,
+            priority,
+            frame,
+            last_update: now,
+            timeout,
+            changed: [now; 512],
+        }
+    }
+
+    fn is_alive(&self, now: Instant) -> bool {
+        now.duration_since(self.last_update) < self.timeout
+    }
+}
+
+/// Who set a channel of a merged universe.
+#[derive(Debug, Clone, Copy)]
+pub enum Owner<'a> {
+    Local,
+    Source(&'a InputSource),
+}
+
+impl Owner<'_> {
+    pub fn label(&self) -> &str {
+        match self {
+            Owner::Local => "local",
+            Owner::Source(source) => &source.label,
+        }
+    }
+}
+
+/// Consecutive channels set by the same source, 1-based.
+#[derive(Debug, Clone, Serialize)]
+pub struct OwnerRange {
+    pub first: u16,
+    pub last: u16,
+    pub owner: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceStatus {
+    pub label: String,
+    pub priority: u8,
+    pub age_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UniverseInputStatus {
+    pub universe: u16,
+    pub sources: Vec<SourceStatus>,
+    pub owners: Vec<OwnerRange>,
+}
+
+/// How sources with the same priority share a universe.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeMode {
+    /// Highest takes precedence.
+    #[default]
+    Htp,
+    /// Latest takes precedence, the source that changed a channel last.
+    Ltp,
+}
+
+/// Our own output as last seen by an LTP merge.
+#[derive(Debug, Clone)]
+struct LocalFrame {
+    frame: [u8; 512],
+    changed: [Instant; 512],
+}
+
+/// External sources per universe, merged with our own output.
+///
+/// The highest priority wins a channel, sources with the same priority are
+/// merged by the universe's `MergeMode`. Our own output takes part with
+/// `local_priority`.
+#[derive(Debug)]
+pub struct InputMerge {
+    universes: BTreeMap<u16, Vec<InputSource>>,
+    modes: BTreeMap<u16, MergeMode>,
+    pub local_priority: u8,
+    /// Updated while merging, which only borrows the show.
+    local: Mutex<BTreeMap<u16, LocalFrame>>,
+}
+
+impl Default for InputMerge {
+    fn default() -> Self {
+        InputMerge {
+            universes: BTreeMap::new(),
+            modes: BTreeMap::new(),
+            local_priority: 100,
+            local: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl Clone for InputMerge {
+    fn clone(&self) -> Self {
+        InputMerge {
+            universes: self.universes.clone(),
+            modes: self.modes.clone(),
+            local_priority: self.local_priority,
+            local: Mutex::new(self.local.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl InputMerge {
+    /// Store a new frame from `source`, replacing its previous one.
+    pub fn update(&mut self, universe: u16, mut source: InputSource) {
+        let now = source.last_update;
+        let sources = self.universes.entry(universe).or_default();
+        if let Some(previous) = sources.iter().find(|s| s.key == source.key) {
+            for channel in 0..512 {
+                if previous.frame[channel] == source.frame[channel] {
+                    source.changed[channel] = previous.changed[channel];
+                }
+            }
+        }
+        sources.retain(|s| s.key != source.key && s.is_alive(now));
+        sources.push(source);
+    }
+
+    pub fn set_mode(&mut self, universe: u16, mode: MergeMode) {
+        self.modes.insert(universe, mode);
+    }
+
+    pub fn mode(&self, universe: u16) -> MergeMode {
+        self.modes.get(&universe).copied().unwrap_or_default()
+    }
+
+    /// When each channel of our own output last changed.
+    fn local_changes(&self, universe: u16, local: &[u8; 512], now: Instant) -> [Instant; 512] {
+        let mut frames = self.local.lock().unwrap();
+        let tracked = frames.entry(universe).or_insert(LocalFrame {
+            frame: *local,
+            changed: [now; 512],
+        });
+        for (channel, &value) in local.iter().enumerate() {
+            if tracked.frame[channel] != value {
+                tracked.frame[channel] = value;
+                tracked.changed[channel] = now;
+            }
+        }
+        tracked.changed
+    }
+
+    /// Drop a source right away, e.g. when it terminated its stream.
+    pub fn remove(&mut self, universe: u16, key: &str) {
+        if let Some(sources) = self.universes.get_mut(&universe) {
+            sources.retain(|s| s.key != key);
+            if sources.is_empty() {
+                self.universes.remove(&universe);
+            }
+        }
+    }
+
+    pub fn sources(&self, universe: u16) -> &[InputSource] {
+        self.universes.get(&universe).map_or(&[], |s| s.as_slice())
+    }
+
+    /// Universes that have, or recently had, an external source.
+    pub fn universe_numbers(&self) -> Vec<u16> {
+        self.universes.keys().copied().collect()
+    }
+
+    /// Merge `local` with the live sources of `universe`, also returning
+    /// which source set each channel.
+    pub fn merge_with_owners(
+        &self,
+        universe: u16,
+        local: &[u8; 512],
+        now: Instant,
+    ) -> ([u8; 512], Vec<Owner<'_>>) {
+        let sources: Vec<&InputSource> = self
+            .sources(universe)
+            .iter()
+            .filter(|s| s.is_alive(now))
+            .collect();
+        let top_priority = sources
+            .iter()
+            .map(|s| s.priority)
+            .chain([self.local_priority])
+            .max()
+            .unwrap_or(self.local_priority);
+
+        let winners: Vec<&InputSource> = sources
+            .into_iter()
+            .filter(|s| s.priority == top_priority)
+            .collect();
+        let local_competes = self.local_priority == top_priority;
+
+        let mut frame = *local;
+        let mut owners = vec![Owner::Local; 512];
+        match self.mode(universe) {
+            MergeMode::Htp => {
+                // When our own output is outranked the first winning source
+                // takes every channel, the others merge on top of it
+                let mut replace = !local_competes;
+                for source in winners {
+                    for channel in 0..512 {
+                        if replace || source.frame[channel] > frame[channel] {
+                            frame[channel] = source.frame[channel];
+                            owners[channel] = Owner::Source(source);
+                        }
+                    }
+                    replace = false;
+                }
+            }
+            MergeMode::Ltp => {
+                let local_changed = self.local_changes(universe, local, now);
+                for channel in 0..512 {
+                    let mut latest = local_competes.then_some(local_changed[channel]);
+                    for source in &winners {
+                        if latest.is_none_or(|t| source.changed[channel] > t) {
+                            frame[channel] = source.frame[channel];
+                            owners[channel] = Owner::Source(source);
+                            latest = Some(source.changed[channel]);
+                        }
+                    }
+                }
+            }
+        }
+        (frame, owners)
+    }
+
+    pub fn merge(&self, universe: u16, local: &[u8; 512], now: Instant) -> [u8; 512] {
+        if !self.universes.contains_key(&universe) && self.mode(universe) == MergeMode::Htp {
+            return *local;
+        }
+        self.merge_with_owners(universe, local, now).0
+    }
+
+    /// Live sources of `universe` and the channel ranges each one owns.
+    pub fn status(&self, universe: u16, local: &[u8; 512], now: Instant) -> UniverseInputStatus {
+        let (_, owners) = self.merge_with_owners(universe, local, now);
+
+        let mut ranges: Vec<OwnerRange> = Vec::new();
+        for (channel, owner) in owners.iter().enumerate() {
+            let channel = channel as u16 + 1;
+            match ranges.last_mut() {
+                Some(range) if range.owner == owner.label() => range.last = channel,
+                _ => ranges.push(OwnerRange {
+                    first: channel,
+                    last: channel,
+                    owner: owner.label().to_string(),
+                }),
+            }
+        }
+
+        UniverseInputStatus {
+            universe,
+            sources: self
+                .sources(universe)
+                .iter()
+                .filter(|s| s.is_alive(now))
+                .map(|s| SourceStatus {
+                    label: s.label.clone(),
+                    priority: s.priority,
+                    age_ms: now.duration_since(s.last_update).as_millis(),
+                })
+                .collect(),
+            owners: ranges,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(2500);
+
+    /// `key` at `priority` sending `values` on the first channels, sent at `at`.
+    fn source(key: &str, priority: u8, values: &[u8], at: Instant) -> InputSource {
+        let mut frame = [0; 512];
+        frame[..values.len()].copy_from_slice(values);
+        let mut source =
+            InputSource::new(key.to_string(), key.to_string(), priority, frame, TIMEOUT);
+        source.last_update = at;
+        source.changed = [at; 512];
+        source
+    }
+
+    fn local_frame(values: &[u8]) -> [u8; 512] {
+        let mut frame = [0; 512];
+        frame[..values.len()].copy_from_slice(values);
+        frame
+    }
+
+    fn owners(merge: &InputMerge, local: &[u8; 512], now: Instant) -> Vec<String> {
+        let (_, owners) = merge.merge_with_owners(1, local, now);
+        owners[..3].iter().map(|o| o.label().to_string()).collect()
+    }
+
+    #[test]
+    fn higher_priority_beats_brighter_sources() {
+        let now = Instant::now();
+        let mut merge = InputMerge::default();
+        merge.update(1, source("desk", 150, &[10], now));
+        merge.update(1, source("guest", 50, &[255, 255, 255], now));
+
+        // Our own output at 100 is outranked too, even on channels desk leaves at 0
+        let local = local_frame(&[255, 255]);
+        assert_eq!(merge.merge(1, &local, now)[..3], [10, 0, 0]);
+        assert_eq!(owners(&merge, &local, now), ["desk", "desk", "desk"]);
+    }
+
+    #[test]
+    fn equal_priorities_merge_highest_takes_precedence() {
+        let now = Instant::now();
+        let mut merge = InputMerge::default();
+        merge.update(1, source("a", 100, &[100, 20], now));
+        merge.update(1, source("b", 100, &[0, 30, 5], now));
+
+        let local = local_frame(&[50, 40]);
+        assert_eq!(merge.merge(1, &local, now)[..3], [100, 40, 5]);
+        assert_eq!(owners(&merge, &local, now), ["a", "local", "b"]);
+
+        // Above our own output the sources only merge among themselves
+        merge.local_priority = 10;
+        assert_eq!(merge.merge(1, &local, now)[..3], [100, 30, 5]);
+        assert_eq!(owners(&merge, &local, now), ["a", "b", "b"]);
+    }
+
+    #[test]
+    fn sources_time_out() {
+        let start = Instant::now();
+        let mut merge = InputMerge::default();
+        merge.update(1, source("desk", 150, &[10], start));
+
+        let local = local_frame(&[255]);
+        let almost = start + TIMEOUT - Duration::from_millis(1);
+        assert_eq!(merge.merge(1, &local, almost)[0], 10);
+        assert_eq!(merge.merge(1, &local, start + TIMEOUT)[0], 255);
+        assert!(merge.status(1, &local, start + TIMEOUT).sources.is_empty());
+
+        // The next update of any source drops it
+        merge.update(1, source("guest", 50, &[1], start + TIMEOUT));
+        assert_eq!(merge.sources(1).len(), 1);
+        assert_eq!(merge.sources(1)[0].key, "guest");
+    }
+
+    /// An LTP merge of universe 1 that has seen our own `local` output at
+    /// `start`.
+    fn ltp(local: &[u8; 512], start: Instant) -> InputMerge {
+        let mut merge = InputMerge::default();
+        merge.set_mode(1, MergeMode::Ltp);
+        merge.merge(1, local, start);
+        merge
+    }
+
+    #[test]
+    fn ltp_gives_each_channel_to_the_latest_change() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let local = local_frame(&[0, 0, 0]);
+        let mut merge = ltp(&local, start);
+
+        // A new source takes every channel it sends
+        merge.update(1, source("a", 100, &[10, 10], at(10)));
+        merge.update(1, source("b", 100, &[20, 20], at(20)));
+        assert_eq!(merge.merge(1, &local, at(20))[..3], [20, 20, 0]);
+        assert_eq!(owners(&merge, &local, at(20)), ["b", "b", "b"]);
+
+        // Then they take turns, each keeping the channels it last changed
+        merge.update(1, source("a", 100, &[30, 10], at(30)));
+        assert_eq!(merge.merge(1, &local, at(30))[..3], [30, 20, 0]);
+        merge.update(1, source("b", 100, &[20, 40], at(40)));
+        assert_eq!(merge.merge(1, &local, at(40))[..3], [30, 40, 0]);
+        merge.update(1, source("a", 100, &[30, 50], at(50)));
+        assert_eq!(merge.merge(1, &local, at(50))[..3], [30, 50, 0]);
+        assert_eq!(owners(&merge, &local, at(50)), ["a", "a", "b"]);
+
+        // Our own output takes part like any other source
+        let changed = local_frame(&[0, 0, 99]);
+        assert_eq!(merge.merge(1, &changed, at(60))[..3], [30, 50, 99]);
+        assert_eq!(owners(&merge, &changed, at(60)), ["a", "a", "local"]);
+    }
+
+    #[test]
+    fn modes_are_chosen_per_universe() {
+        let start = Instant::now();
+        let mut merge = ltp(&[0; 512], start);
+        for universe in [1, 2] {
+            merge.update(universe, source("a", 100, &[200], start));
+            merge.update(universe, source("b", 100, &[50], start + TIMEOUT / 2));
+        }
+
+        let now = start + TIMEOUT / 2;
+        assert_eq!(merge.mode(2), MergeMode::Htp);
+        assert_eq!(merge.merge(1, &[0; 512], now)[0], 50);
+        assert_eq!(merge.merge(2, &[0; 512], now)[0], 200);
+    }
+
+    #[test]
+    fn expired_ltp_sources_no_longer_contribute() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let local = local_frame(&[0]);
+        let mut merge = ltp(&local, start);
+        merge.update(1, source("b", 100, &[20], at(10)));
+        merge.update(1, source("a", 100, &[10], at(20)));
+        assert_eq!(merge.merge(1, &local, at(20))[0], 10);
+
+        // b keeps sending the same value, which doesn't take the channel back
+        merge.update(1, source("b", 100, &[20], at(2000)));
+        assert_eq!(merge.merge(1, &local, at(2000))[0], 10);
+
+        // until a goes quiet
+        assert_eq!(merge.merge(1, &local, at(20) + TIMEOUT)[0], 20);
+        assert_eq!(merge.merge(1, &local, at(2000) + TIMEOUT)[0], 0);
+        assert_eq!(owners(&merge, &local, at(2000) + TIMEOUT)[0], "local");
+    }
+
+    #[test]
+    fn status_reports_who_owns_each_range() {
+        let start = Instant::now();
+        let mut merge = InputMerge::default();
+        merge.update(1, source("a", 100, &[200, 200, 200], start));
+        let mut b = [0; 12];
+        b[9..].fill(255);
+        merge.update(1, source("b", 100, &b, start));
+
+        let status = merge.status(1, &[0; 512], start + Duration::from_millis(40));
+        let ranges: Vec<(u16, u16, &str)> = status
+            .owners
+            .iter()
+            .map(|r| (r.first, r.last, r.owner.as_str()))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (1, 3, "a"),
+                (4, 9, "local"),
+                (10, 12, "b"),
+                (13, 512, "local")
+            ]
+        );
+        assert_eq!(status.sources.len(), 2);
+        assert!(status.sources.iter().all(|s| s.age_ms == 40));
+    }
+}
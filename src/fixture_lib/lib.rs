@@ -3,3 +3,27 @@ pub mod fixture;
 
 #[path = "universe.rs"]
 pub mod universe;
+
+#[path = "fixture_type.rs"]
+pub mod fixture_type;
+
+#[path = "ofl.rs"]
+pub mod ofl;
+
+#[path = "input.rs"]
+pub mod input;
+
+#[path = "preset.rs"]
+pub mod preset;
+
+#[path = "cue.rs"]
+pub mod cue;
+
+#[path = "programmer.rs"]
+pub mod programmer;
+
+#[path = "selection.rs"]
+pub mod selection;
+
+#[path = "show.rs"]
+pub mod show;
@@ -0,0 +1,781 @@
+//! Import of Open Fixture Library definitions.
+//!
+//! Reads fixtures from a local copy of the OFL `fixtures/` directory, laid out
+//! as `<manufacturer-key>/<fixture-key>.json` with an optional
+//! `manufacturers.json` next to the manufacturer directories.
+
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::lib::{
+    fixture::{
+        Attribute, Axis, Color, ColorWheel, CustomValue, Dimmer, FineChannel, FixtureComponent,
+        Focus, GoboWheel, Position, WheelSlot,
+    },
+    fixture_type::{FixtureMode, FixtureType},
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OflFixture {
+    name: String,
+    #[serde(default)]
+    available_channels: HashMap<String, OflChannel>,
+    #[serde(default)]
+    wheels: HashMap<String, OflWheel>,
+    modes: Vec<OflMode>,
+    physical: Option<OflPhysical>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OflPhysical {
+    focus: Option<OflFocus>,
+}
+
+/// `panMax`/`tiltMax` are degrees, or "Infinity" for endless rotation.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OflFocus {
+    pan_max: Option<serde_json::Value>,
+    tilt_max: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OflChannel {
+    #[serde(default)]
+    fine_channel_aliases: Vec<String>,
+    capability: Option<OflCapability>,
+    #[serde(default)]
+    capabilities: Vec<OflCapability>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OflCapability {
+    #[serde(rename = "type")]
+    kind: String,
+    dmx_range: Option<[u16; 2]>,
+    color: Option<String>,
+    wheel: Option<String>,
+    slot_number: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OflWheel {
+    slots: Vec<OflWheelSlot>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OflWheelSlot {
+    #[serde(rename = "type")]
+    kind: String,
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OflMode {
+    name: String,
+    short_name: Option<String>,
+    channels: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OflManufacturer {
+    name: String,
+}
+
+/// A channel that has no matching `FixtureComponent`. It is patched as a
+/// `Placeholder` so the following channels keep their offsets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnmappedChannel {
+    pub mode: String,
+    /// 1-based channel offset within the mode.
+    pub offset: usize,
+    pub channel: String,
+    pub reason: String,
+}
+
+impl fmt::Display for UnmappedChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mode '{}' channel {} '{}': {}",
+            self.mode, self.offset, self.channel, self.reason
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct OflImport {
+    pub fixture_type: FixtureType,
+    pub unmapped: Vec<UnmappedChannel>,
+}
+
+#[derive(Debug)]
+pub enum OflError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_json::Error),
+    UnsupportedMode { mode: String, reason: String },
+}
+
+impl fmt::Display for OflError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OflError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            OflError::Parse(path, e) => {
+                write!(f, "invalid OFL fixture {}: {}", path.display(), e)
+            }
+            OflError::UnsupportedMode { mode, reason } => {
+                write!(f, "OFL mode '{}' is not supported: {}", mode, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OflError {}
+
+/// Import `<manufacturer-key>/<fixture-key>` from an OFL fixtures directory.
+pub fn import_fixture(directory: &Path, key: &str) -> Result<OflImport, OflError> {
+    let path = directory.join(format!("{}.json", key));
+    let text = fs::read_to_string(&path).map_err(|e| OflError::Io(path.clone(), e))?;
+    let fixture: OflFixture =
+        serde_json::from_str(&text).map_err(|e| OflError::Parse(path.clone(), e))?;
+
+    let manufacturer_key = key.split('/').next().unwrap_or(key);
+    let manufacturer = manufacturer_name(directory, manufacturer_key);
+
+    let mut modes = Vec::new();
+    let mut unmapped = Vec::new();
+    for mode in &fixture.modes {
+        let (mode, mut mode_unmapped) = convert_mode(&fixture, mode)?;
+        modes.push(mode);
+        unmapped.append(&mut mode_unmapped);
+    }
+    if let Some(focus) = fixture.physical.as_ref().and_then(|p| p.focus.as_ref()) {
+        let degrees = |value: &Option<serde_json::Value>| value.as_ref().and_then(|v| v.as_f64());
+        set_pan_tilt_range(
+            &mut modes,
+            degrees(&focus.pan_max),
+            degrees(&focus.tilt_max),
+        );
+    }
+
+    Ok(OflImport {
+        fixture_type: FixtureType {
+            manufacturer,
+            model: fixture.name.clone(),
+            modes,
+        },
+        unmapped,
+    })
+}
+
+/// Store the fixture's pan and tilt range on the position components of
+/// every mode.
+fn set_pan_tilt_range(modes: &mut [FixtureMode], pan: Option<f64>, tilt: Option<f64>) {
+    for component in modes.iter_mut().flat_map(|m| m.channels.iter_mut()) {
+        match component {
+            FixtureComponent::Position(p) => {
+                p.pan_range = pan;
+                p.tilt_range = tilt;
+            }
+            FixtureComponent::Pan(a) => a.range = pan,
+            FixtureComponent::Tilt(a) => a.range = tilt,
+            _ => {}
+        }
+    }
+}
+
+/// Display name from `manufacturers.json`, falling back to the directory key.
+fn manufacturer_name(directory: &Path, key: &str) -> String {
+    fs::read_to_string(directory.join("manufacturers.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str::<HashMap<String, serde_json::Value>>(&text).ok())
+        .and_then(|manufacturers| {
+            serde_json::from_value::<OflManufacturer>(manufacturers.get(key)?.clone()).ok()
+        })
+        .map(|m| m.name)
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// How a channel of the mode is going to be represented.
+enum Slot<'a> {
+    Coarse(&'a str, &'a OflChannel),
+    Fine(&'a str, &'a OflChannel),
+    Empty,
+}
+
+fn convert_mode(
+    fixture: &OflFixture,
+    mode: &OflMode,
+) -> Result<(FixtureMode, Vec<UnmappedChannel>), OflError> {
+    let mode_name = mode.short_name.clone().unwrap_or_else(|| mode.name.clone());
+    let mut unmapped = Vec::new();
+
+    let mut slots = Vec::new();
+    for (i, entry) in mode.channels.iter().enumerate() {
+        let slot = match entry {
+            serde_json::Value::Null => Slot::Empty,
+            serde_json::Value::String(key) => match lookup_channel(fixture, key) {
+                Some((coarse_key, channel, false)) => Slot::Coarse(coarse_key, channel),
+                Some((coarse_key, channel, true)) => {
+                    let fine_index = channel
+                        .fine_channel_aliases
+                        .iter()
+                        .position(|alias| alias == key);
+                    if fine_index == Some(0) {
+                        Slot::Fine(coarse_key, channel)
+                    } else {
+                        unmapped.push(UnmappedChannel {
+                            mode: mode_name.clone(),
+                            offset: i + 1,
+                            channel: key.clone(),
+                            reason: "only 16 bit resolution is supported".to_string(),
+                        });
+                        Slot::Empty
+                    }
+                }
+                None => {
+                    unmapped.push(UnmappedChannel {
+                        mode: mode_name.clone(),
+                        offset: i + 1,
+                        channel: key.clone(),
+                        reason: "channel is not defined in availableChannels".to_string(),
+                    });
+                    Slot::Empty
+                }
+            },
+            _ => {
+                return Err(OflError::UnsupportedMode {
+                    mode: mode_name,
+                    reason: "matrix channel insertion".to_string(),
+                });
+            }
+        };
+        slots.push(slot);
+    }
+
+    let mut channels = Vec::new();
+    let mut has_rgb = false;
+    let mut i = 0;
+    while i < slots.len() {
+        // Pan, Pan fine, Tilt, Tilt fine in a row is exactly a Position component
+        if let [
+            Slot::Coarse(_, a),
+            Slot::Fine(_, b),
+            Slot::Coarse(_, c),
+            Slot::Fine(_, d),
+            ..,
+        ] = &slots[i..]
+            && channel_kind(a) == "Pan"
+            && channel_kind(b) == "Pan"
+            && channel_kind(c) == "Tilt"
+            && channel_kind(d) == "Tilt"
+        {
+            channels.push(FixtureComponent::Position(Position::default()));
+            i += 4;
+            continue;
+        }
+
+        // Red, Green, Blue in a row is a Color component
+        if let [
+            Slot::Coarse(_, r),
+            Slot::Coarse(_, g),
+            Slot::Coarse(_, b),
+            ..,
+        ] = &slots[i..]
+            && intensity_color(r) == Some("Red")
+            && intensity_color(g) == Some("Green")
+            && intensity_color(b) == Some("Blue")
+        {
+            channels.push(FixtureComponent::Color(Color::default()));
+            has_rgb = true;
+            i += 3;
+            continue;
+        }
+
+        let component = match &slots[i] {
+            Slot::Empty => FixtureComponent::Zero,
+            Slot::Coarse(key, channel) => match map_channel(fixture, key, channel) {
+                Ok(component) => component,
+                Err(reason) => {
+                    unmapped.push(UnmappedChannel {
+                        mode: mode_name.clone(),
+                        offset: i + 1,
+                        channel: key.to_string(),
+                        reason,
+                    });
+                    FixtureComponent::Placeholder
+                }
+            },
+            Slot::Fine(key, channel) => match map_channel(fixture, key, channel) {
+                Ok(coarse) => match attribute_of(&coarse, has_rgb) {
+                    Some(attribute) => FixtureComponent::Fine(FineChannel { attribute }),
+                    None => FixtureComponent::Zero,
+                },
+                Err(reason) => {
+                    unmapped.push(UnmappedChannel {
+                        mode: mode_name.clone(),
+                        offset: i + 1,
+                        channel: format!("{} fine", key),
+                        reason,
+                    });
+                    FixtureComponent::Placeholder
+                }
+            },
+        };
+        channels.push(component);
+        i += 1;
+    }
+    unmapped.sort_by_key(|channel| channel.offset);
+
+    Ok((
+        FixtureMode {
+            name: mode_name,
+            channels,
+        },
+        unmapped,
+    ))
+}
+
+/// Find a channel by key or fine alias. Returns the coarse key, the channel
+/// and whether `key` referred to a fine channel.
+fn lookup_channel<'a>(
+    fixture: &'a OflFixture,
+    key: &str,
+) -> Option<(&'a str, &'a OflChannel, bool)> {
+    if let Some((coarse_key, channel)) = fixture.available_channels.get_key_value(key) {
+        return Some((coarse_key, channel, false));
+    }
+    fixture
+        .available_channels
+        .iter()
+        .find(|(_, channel)| {
+            channel
+                .fine_channel_aliases
+                .iter()
+                .any(|alias| alias == key)
+        })
+        .map(|(coarse_key, channel)| (coarse_key.as_str(), channel, true))
+}
+
+fn capabilities(channel: &OflChannel) -> impl Iterator<Item = &OflCapability> {
+    channel.capability.iter().chain(channel.capabilities.iter())
+}
+
+/// The capability type that describes the channel: the first one that
+/// actually does something, wheel capabilities taking precedence.
+fn channel_kind(channel: &OflChannel) -> &str {
+    if let Some(wheel) = capabilities(channel).find(|c| c.kind.starts_with("Wheel")) {
+        return &wheel.kind;
+    }
+    capabilities(channel)
+        .map(|c| c.kind.as_str())
+        .find(|kind| *kind != "NoFunction")
+        .unwrap_or("NoFunction")
+}
+
+fn intensity_color(channel: &OflChannel) -> Option<&str> {
+    if channel_kind(channel) != "ColorIntensity" {
+        return None;
+    }
+    capabilities(channel).find_map(|c| c.color.as_deref())
+}
+
+/// Capability types that are kept as a named `CustomValue` channel.
+const CUSTOM_VALUE_TYPES: &[&str] = &[
+    "ColorPreset",
+    "ColorTemperature",
+    "Effect",
+    "EffectDuration",
+    "EffectSpeed",
+    "Fog",
+    "FogOutput",
+    "FogType",
+    "Frost",
+    "FrostEffect",
+    "Iris",
+    "IrisEffect",
+    "PanTiltSpeed",
+    "Prism",
+    "PrismRotation",
+    "Rotation",
+    "SoundSensitivity",
+    "Speed",
+    "StrobeDuration",
+    "StrobeSpeed",
+    "Zoom",
+];
+
+fn map_channel(
+    fixture: &OflFixture,
+    key: &str,
+    channel: &OflChannel,
+) -> Result<FixtureComponent, String> {
+    let kind = channel_kind(channel);
+    match kind {
+        "Intensity" => Ok(FixtureComponent::Dimmer(Dimmer::default())),
+        "Pan" => Ok(FixtureComponent::Pan(Axis::default())),
+        "Tilt" => Ok(FixtureComponent::Tilt(Axis::default())),
+        "Focus" => Ok(FixtureComponent::Focus(Focus::default())),
+        "ShutterStrobe" => Ok(custom_value("strobe")),
+        "NoFunction" => Ok(FixtureComponent::Zero),
+        "ColorIntensity" => {
+            let color = capabilities(channel)
+                .find_map(|c| c.color.as_deref())
+                .unwrap_or(key);
+            Ok(custom_value(&color_name(color)))
+        }
+        "WheelSlot" | "WheelShake" | "WheelRotation" | "WheelSlotRotation" => {
+            let wheel_name = capabilities(channel)
+                .find_map(|c| c.wheel.as_deref())
+                .unwrap_or(key);
+            let slots = wheel_slots(fixture, wheel_name, channel);
+            let is_gobo = fixture
+                .wheels
+                .get(wheel_name)
+                .is_some_and(|w| w.slots.iter().any(|s| s.kind.starts_with("Gobo")));
+            if is_gobo {
+                Ok(FixtureComponent::Gobo(GoboWheel { index: 0, slots }))
+            } else {
+                Ok(FixtureComponent::ColorWheel(ColorWheel { index: 0, slots }))
+            }
+        }
+        kind if CUSTOM_VALUE_TYPES.contains(&kind) => Ok(custom_value(&snake_case(key))),
+        kind => Err(format!("capability type {} cannot be mapped", kind)),
+    }
+}
+
+fn custom_value(name: &str) -> FixtureComponent {
+    FixtureComponent::CustomValue(CustomValue {
+        name: name.to_string(),
+        value: 0,
+    })
+}
+
+/// OFL color names to the custom value names used by our own patches.
+fn color_name(color: &str) -> String {
+    match color {
+        "Cold White" => "cool_white".to_string(),
+        color => snake_case(color),
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Slot names of `wheel_name` with the DMX value that selects each slot.
+fn wheel_slots(fixture: &OflFixture, wheel_name: &str, channel: &OflChannel) -> Vec<WheelSlot> {
+    let Some(wheel) = fixture.wheels.get(wheel_name) else {
+        return Vec::new();
+    };
+
+    capabilities(channel)
+        .filter(|c| c.kind == "WheelSlot" && c.slot_number.is_some_and(|n| n.fract() == 0.0))
+        .filter_map(|c| {
+            let number = c.slot_number? as usize;
+            let slot = wheel.slots.get(number.checked_sub(1)?)?;
+            let name = match (&slot.name, slot.kind.as_str()) {
+                (Some(name), _) => name.clone(),
+                (None, "Gobo") => format!("Gobo {}", number),
+                (None, kind) => kind.to_string(),
+            };
+            Some(WheelSlot {
+                name,
+                dmx_value: c.dmx_range.map(|r| r[0].min(255) as u8).unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+/// The attribute a fine channel following `coarse` refines. With `has_rgb`
+/// the red, green and blue channels live in a `Color` component.
+fn attribute_of(coarse: &FixtureComponent, has_rgb: bool) -> Option<Attribute> {
+    match coarse {
+        FixtureComponent::CustomValue(c) if has_rgb && c.name == "red" => Some(Attribute::Red),
+        FixtureComponent::CustomValue(c) if has_rgb && c.name == "green" => Some(Attribute::Green),
+        FixtureComponent::CustomValue(c) if has_rgb && c.name == "blue" => Some(Attribute::Blue),
+        FixtureComponent::Dimmer(_) => Some(Attribute::Dimmer),
+        FixtureComponent::Pan(_) => Some(Attribute::Pan),
+        FixtureComponent::Tilt(_) => Some(Attribute::Tilt),
+        FixtureComponent::Focus(_) => Some(Attribute::Focus),
+        FixtureComponent::ColorWheel(_) => Some(Attribute::ColorWheel),
+        FixtureComponent::Gobo(_) => Some(Attribute::Gobo),
+        FixtureComponent::CustomValue(c) => Some(Attribute::Custom(c.name.clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A small spot with every kind of channel the importer knows, patched
+    /// in `modes`.
+    fn spot(modes: serde_json::Value) -> serde_json::Value {
+        json!({
+            "name": "Spot 1",
+            "availableChannels": {
+                "Pan": { "fineChannelAliases": ["Pan fine"], "capability": { "type": "Pan" } },
+                "Tilt": { "fineChannelAliases": ["Tilt fine"], "capability": { "type": "Tilt" } },
+                "Dimmer": {
+                    "fineChannelAliases": ["Dimmer fine", "Dimmer fine^2"],
+                    "capability": { "type": "Intensity" }
+                },
+                "Red": { "fineChannelAliases": ["Red fine"], "capability": { "type": "ColorIntensity", "color": "Red" } },
+                "Green": { "capability": { "type": "ColorIntensity", "color": "Green" } },
+                "Blue": { "capability": { "type": "ColorIntensity", "color": "Blue" } },
+                "Cold": { "capability": { "type": "ColorIntensity", "color": "Cold White" } },
+                "Strobe": { "capability": { "type": "ShutterStrobe" } },
+                "Zoom": { "fineChannelAliases": ["Zoom fine"], "capability": { "type": "Zoom" } },
+                "Lamp Control": { "capability": { "type": "Maintenance" } },
+                "Color Wheel": {
+                    "capabilities": [
+                        { "dmxRange": [0, 9], "type": "WheelSlot", "slotNumber": 1 },
+                        { "dmxRange": [10, 19], "type": "WheelSlot", "slotNumber": 1.5 },
+                        { "dmxRange": [20, 29], "type": "WheelSlot", "slotNumber": 2 }
+                    ]
+                },
+                "Gobos": {
+                    "capabilities": [
+                        { "dmxRange": [0, 63], "type": "WheelSlot", "wheel": "Gobo Wheel", "slotNumber": 1 },
+                        { "dmxRange": [64, 127], "type": "WheelSlot", "wheel": "Gobo Wheel", "slotNumber": 2 },
+                        { "dmxRange": [128, 255], "type": "WheelShake", "wheel": "Gobo Wheel", "slotNumber": 2 }
+                    ]
+                }
+            },
+            "wheels": {
+                "Color Wheel": { "slots": [{ "type": "Open" }, { "type": "Color", "name": "Deep Red" }] },
+                "Gobo Wheel": { "slots": [{ "type": "Open" }, { "type": "Gobo" }] }
+            },
+            "physical": { "focus": { "panMax": 540, "tiltMax": 270 } },
+            "modes": modes
+        })
+    }
+
+    /// Import `fixture` from a throwaway OFL directory as `acme/<name>`.
+    fn import_json(name: &str, fixture: serde_json::Value) -> Result<OflImport, OflError> {
+        let directory =
+            std::env::temp_dir().join(format!("illumix-ofl-{}-{}", std::process::id(), name));
+        fs::create_dir_all(directory.join("acme")).unwrap();
+        fs::write(
+            directory.join("manufacturers.json"),
+            json!({ "acme": { "name": "Acme Lighting" } }).to_string(),
+        )
+        .unwrap();
+        fs::write(
+            directory.join(format!("acme/{}.json", name)),
+            fixture.to_string(),
+        )
+        .unwrap();
+
+        let import = import_fixture(&directory, &format!("acme/{}", name));
+        fs::remove_dir_all(&directory).unwrap();
+        import
+    }
+
+    fn fine(attribute: Attribute) -> FixtureComponent {
+        FixtureComponent::Fine(FineChannel { attribute })
+    }
+
+    #[test]
+    fn maps_pan_tilt_color_and_dimmer_channels() {
+        let import = import_json(
+            "basic",
+            spot(json!([{
+                "name": "Basic",
+                "channels": ["Pan", "Pan fine", "Tilt", "Tilt fine", "Dimmer", "Red", "Green", "Blue", "Cold", "Strobe"]
+            }])),
+        )
+        .unwrap();
+
+        assert_eq!(import.fixture_type.manufacturer, "Acme Lighting");
+        assert_eq!(import.fixture_type.model, "Spot 1");
+        assert_eq!(import.unmapped, Vec::new());
+        assert_eq!(
+            import.fixture_type.modes[0].channels,
+            vec![
+                FixtureComponent::Position(Position {
+                    pan_range: Some(540.0),
+                    tilt_range: Some(270.0),
+                    ..Position::default()
+                }),
+                FixtureComponent::Dimmer(Dimmer::default()),
+                FixtureComponent::Color(Color::default()),
+                custom_value("cool_white"),
+                custom_value("strobe"),
+            ]
+        );
+    }
+
+    #[test]
+    fn fine_channels_follow_their_coarse_attribute() {
+        let import = import_json(
+            "fine",
+            spot(json!([{
+                "name": "Fine",
+                "channels": [
+                    "Pan", "Tilt", "Pan fine", "Tilt fine",
+                    "Dimmer", "Dimmer fine",
+                    "Red", "Green", "Blue", "Red fine",
+                    "Zoom", "Zoom fine"
+                ]
+            }])),
+        )
+        .unwrap();
+
+        assert_eq!(import.unmapped, Vec::new());
+        assert_eq!(
+            import.fixture_type.modes[0].channels,
+            vec![
+                // Not in Position order, so one axis per channel
+                FixtureComponent::Pan(Axis {
+                    value: 0,
+                    range: Some(540.0)
+                }),
+                FixtureComponent::Tilt(Axis {
+                    value: 0,
+                    range: Some(270.0)
+                }),
+                fine(Attribute::Pan),
+                fine(Attribute::Tilt),
+                FixtureComponent::Dimmer(Dimmer::default()),
+                fine(Attribute::Dimmer),
+                FixtureComponent::Color(Color::default()),
+                fine(Attribute::Red),
+                custom_value("zoom"),
+                fine(Attribute::Custom("zoom".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn wheel_slots_are_named_from_the_wheel() {
+        let import = import_json(
+            "wheels",
+            spot(json!([{ "name": "Wheels", "channels": ["Color Wheel", "Gobos"] }])),
+        )
+        .unwrap();
+
+        let slot = |name: &str, dmx_value| WheelSlot {
+            name: name.to_string(),
+            dmx_value,
+        };
+        assert_eq!(
+            import.fixture_type.modes[0].channels,
+            vec![
+                // Split colors between two slots are left out
+                FixtureComponent::ColorWheel(ColorWheel {
+                    index: 0,
+                    slots: vec![slot("Open", 0), slot("Deep Red", 20)],
+                }),
+                // The wheel comes from the capabilities, not the channel key
+                FixtureComponent::Gobo(GoboWheel {
+                    index: 0,
+                    slots: vec![slot("Open", 0), slot("Gobo 2", 64)],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_mode_is_imported_by_short_name() {
+        let import = import_json(
+            "modes",
+            spot(json!([
+                { "name": "3 channel", "shortName": "3ch", "channels": ["Red", "Green", "Blue"] },
+                { "name": "Dimmer", "channels": ["Dimmer", null] }
+            ])),
+        )
+        .unwrap();
+
+        let modes = &import.fixture_type.modes;
+        assert_eq!(modes.len(), 2);
+        assert_eq!(modes[0].name, "3ch");
+        assert_eq!(
+            modes[0].channels,
+            vec![FixtureComponent::Color(Color::default())]
+        );
+        assert_eq!(modes[1].name, "Dimmer");
+        assert_eq!(
+            modes[1].channels,
+            vec![
+                FixtureComponent::Dimmer(Dimmer::default()),
+                FixtureComponent::Zero
+            ]
+        );
+
+        let matrix = import_json(
+            "matrix",
+            spot(json!([{
+                "name": "Pixels",
+                "channels": [{ "insert": "matrixChannels", "repeatFor": "eachPixelABC" }]
+            }])),
+        );
+        assert!(matches!(
+            matrix,
+            Err(OflError::UnsupportedMode { mode, .. }) if mode == "Pixels"
+        ));
+    }
+
+    #[test]
+    fn unmapped_channels_are_reported_and_keep_their_offset() {
+        let import = import_json(
+            "unmapped",
+            spot(json!([{
+                "name": "Full",
+                "channels": ["Dimmer", "Dimmer fine", "Dimmer fine^2", "Lamp Control", "Fog Level", "Strobe"]
+            }])),
+        )
+        .unwrap();
+
+        let unmapped = |offset, channel: &str, reason: &str| UnmappedChannel {
+            mode: "Full".to_string(),
+            offset,
+            channel: channel.to_string(),
+            reason: reason.to_string(),
+        };
+        assert_eq!(
+            import.unmapped,
+            vec![
+                unmapped(3, "Dimmer fine^2", "only 16 bit resolution is supported"),
+                unmapped(
+                    4,
+                    "Lamp Control",
+                    "capability type Maintenance cannot be mapped"
+                ),
+                unmapped(
+                    5,
+                    "Fog Level",
+                    "channel is not defined in availableChannels"
+                ),
+            ]
+        );
+        assert_eq!(
+            import.fixture_type.modes[0].channels,
+            vec![
+                FixtureComponent::Dimmer(Dimmer::default()),
+                fine(Attribute::Dimmer),
+                FixtureComponent::Zero,
+                FixtureComponent::Placeholder,
+                FixtureComponent::Zero,
+                custom_value("strobe"),
+            ]
+        );
+    }
+}
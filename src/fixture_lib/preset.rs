@@ -0,0 +1,196 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::lib::{fixture::Attribute, selection::Selection, show::Show};
+
+/// One attribute of one fixture, as stored in a preset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresetValue {
+    pub fixture_id: u8,
+    pub attribute: Attribute,
+    /// 16 bit, 8 bit attributes use the high byte.
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    #[serde(default)]
+    pub name: String,
+    pub values: Vec<PresetValue>,
+}
+
+impl Preset {
+    /// The current values of the fixtures in `selection`, every fixture when
+    /// not given, limited to `attributes` when given.
+    pub fn capture(
+        show: &Show,
+        selection: Option<&Selection>,
+        attributes: Option<&[Attribute]>,
+    ) -> Preset {
+        let fixture_ids = match selection {
+            Some(selection) => selection.fixture_ids(show),
+            None => show.fixtures().map(|f| f.id).collect(),
+        };
+
+        let mut values = Vec::new();
+        for fixture in fixture_ids
+            .into_iter()
+            .filter_map(|id| show.get_fixture_by_id(id))
+        {
+            for attribute in fixture.attributes() {
+                if attributes.is_some_and(|wanted| !wanted.contains(&attribute)) {
+                    continue;
+                }
+                if let Some(value) = fixture.get_attribute(&attribute) {
+                    values.push(PresetValue {
+                        fixture_id: fixture.id,
+                        attribute,
+                        value,
+                    });
+                }
+            }
+        }
+        Preset {
+            name: String::new(),
+            values,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PresetError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_json::Error),
+    Serialize(PathBuf, serde_json::Error),
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresetError::Io(path, e) => write!(f, "could not access {}: {}", path.display(), e),
+            PresetError::Parse(path, e) => {
+                write!(f, "invalid preset file {}: {}", path.display(), e)
+            }
+            PresetError::Serialize(path, e) => {
+                write!(f, "could not encode presets for {}: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PresetError {}
+
+/// Numbered presets, written to `path` whenever one is stored.
+#[derive(Debug, Clone, Default)]
+pub struct PresetStore {
+    /// Kept in memory only when not set.
+    path: Option<PathBuf>,
+    presets: BTreeMap<u32, Preset>,
+}
+
+impl PresetStore {
+    /// Load the presets from `path`, starting empty if it doesn't exist yet.
+    pub fn load(path: &Path) -> Result<PresetStore, PresetError> {
+        let presets = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| PresetError::Parse(path.to_path_buf(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(PresetError::Io(path.to_path_buf(), e)),
+        };
+        Ok(PresetStore {
+            path: Some(path.to_path_buf()),
+            presets,
+        })
+    }
+
+    pub fn get(&self, number: u32) -> Option<&Preset> {
+        self.presets.get(&number)
+    }
+
+    pub fn presets(&self) -> &BTreeMap<u32, Preset> {
+        &self.presets
+    }
+
+    /// Store `preset` as number `number`, replacing what was there, and save
+    /// the store.
+    pub fn store(&mut self, number: u32, preset: Preset) -> Result<(), PresetError> {
+        self.presets.insert(number, preset);
+        self.save()
+    }
+
+    /// Write to a temporary file first so a crash can't leave half a file.
+    fn save(&self) -> Result<(), PresetError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let text = serde_json::to_string_pretty(&self.presets)
+            .map_err(|e| PresetError::Serialize(path.clone(), e))?;
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, text)
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|e| PresetError::Io(path.clone(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patching::get_show;
+
+    #[test]
+    fn capture_selected_fixtures_and_attributes() {
+        let show = get_show();
+
+        let preset = Preset::capture(&show, Some(&"moving_heads".into()), None);
+        assert!(preset.values.iter().all(|v| [8, 9].contains(&v.fixture_id)));
+        let attributes: Vec<&Attribute> = preset
+            .values
+            .iter()
+            .filter(|v| v.fixture_id == 8)
+            .map(|v| &v.attribute)
+            .collect();
+        assert_eq!(
+            attributes,
+            [
+                &Attribute::Pan,
+                &Attribute::Tilt,
+                &Attribute::ColorWheel,
+                &Attribute::Gobo,
+                &Attribute::Focus,
+                &Attribute::Dimmer,
+            ]
+        );
+
+        let preset = Preset::capture(&show, None, Some(&[Attribute::Dimmer]));
+        assert_eq!(preset.values.len(), 9);
+        assert!(preset.values.iter().all(|v| v.value == 65535));
+    }
+
+    #[test]
+    fn presets_survive_a_reload() {
+        let path =
+            std::env::temp_dir().join(format!("illumix-presets-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut store = PresetStore::load(&path).unwrap();
+        assert!(store.presets().is_empty());
+        let preset = Preset {
+            name: "Warm front".to_string(),
+            values: vec![PresetValue {
+                fixture_id: 1,
+                attribute: Attribute::Custom("warm_white".to_string()),
+                value: 40000,
+            }],
+        };
+        store.store(3, preset.clone()).unwrap();
+
+        let reloaded = PresetStore::load(&path).unwrap();
+        assert_eq!(reloaded.get(3), Some(&preset));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+
+use crate::lib::{fixture::Attribute, preset::PresetValue, selection::Selection, show::Show};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgrammerCommand {
+    /// Replace the selection.
+    Select(Selection),
+    /// Program `attribute` of every selected fixture that has it.
+    Set { attribute: Attribute, value: u16 },
+    /// Give the selected fixtures back to the playbacks.
+    Release,
+    /// Release everything and drop the selection.
+    Clear,
+    /// Store the programmed values.
+    Record(RecordTarget),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordTarget {
+    Preset(u32),
+    Cue(u32),
+}
+
+#[derive(Debug, Clone)]
+struct Programmed {
+    value: PresetValue,
+    /// What the fixture had before it was programmed, restored on release.
+    base: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgrammerStatus {
+    pub selection: Vec<u8>,
+    pub values: Vec<PresetValue>,
+}
+
+/// Values set by hand. The render loop merges them above every playback, so
+/// they win until they are released.
+#[derive(Debug, Clone, Default)]
+pub struct Programmer {
+    /// Fixture ids in selection order.
+    selection: Vec<u8>,
+    /// In the order they were first set.
+    values: Vec<Programmed>,
+}
+
+impl Programmer {
+    pub fn select(&mut self, fixture_ids: Vec<u8>) {
+        self.selection = fixture_ids;
+    }
+
+    pub fn selection(&self) -> &[u8] {
+        &self.selection
+    }
+
+    pub fn values(&self) -> Vec<PresetValue> {
+        self.values.iter().map(|p| p.value.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn status(&self) -> ProgrammerStatus {
+        ProgrammerStatus {
+            selection: self.selection.clone(),
+            values: self.values(),
+        }
+    }
+
+    fn set(&mut self, fixture_id: u8, attribute: &Attribute, value: u16, base: u16) {
+        match self
+            .values
+            .iter_mut()
+            .find(|p| p.value.fixture_id == fixture_id && p.value.attribute == *attribute)
+        {
+            Some(programmed) => programmed.value.value = value,
+            None => self.values.push(Programmed {
+                value: PresetValue {
+                    fixture_id,
+                    attribute: attribute.clone(),
+                    value,
+                },
+                base,
+            }),
+        }
+    }
+}
+
+/// Program `attribute` of each fixture in `fixture_ids` that has it. Returns
+/// how many fixtures were programmed.
+pub fn program(show: &mut Show, fixture_ids: &[u8], attribute: &Attribute, value: u16) -> usize {
+    let mut programmed = 0;
+    for &fixture_id in fixture_ids {
+        let Some(current) = show
+            .get_fixture_by_id(fixture_id)
+            .and_then(|fixture| fixture.get_attribute(attribute))
+        else {
+            continue;
+        };
+        show.programmer.set(fixture_id, attribute, value, current);
+        programmed += 1;
+    }
+    programmed
+}
+
+/// Remove `attributes`, or every attribute when not given, of `fixture_ids`
+/// from the programmer. Fixtures get back the values they had before, until
+/// a playback sets them again. Returns how many values were released.
+pub fn release(show: &mut Show, fixture_ids: &[u8], attributes: Option<&[Attribute]>) -> usize {
+    let (released, kept) = std::mem::take(&mut show.programmer.values)
+        .into_iter()
+        .partition::<Vec<_>, _>(|p| {
+            fixture_ids.contains(&p.value.fixture_id)
+                && attributes.is_none_or(|wanted| wanted.contains(&p.value.attribute))
+        });
+    show.programmer.values = kept;
+    for programmed in &released {
+        if let Some(fixture) = show.get_fixture_by_id_mut(programmed.value.fixture_id) {
+            fixture.set_attribute(&programmed.value.attribute, programmed.base);
+        }
+    }
+    released.len()
+}
+
+/// Release every programmed value and drop the selection.
+pub fn clear(show: &mut Show) -> usize {
+    let fixture_ids: Vec<u8> = show.fixtures().map(|f| f.id).collect();
+    show.programmer.select(Vec::new());
+    release(show, &fixture_ids, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::{effect::ColorSwapEffect, patching::get_show, render::render};
+
+    fn warm_white(show: &Show, fixture_id: u8) -> u16 {
+        show.get_fixture_by_id(fixture_id)
+            .unwrap()
+            .get_attribute(&Attribute::Custom("warm_white".to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn programmer_overrides_effects_until_released() {
+        let mut show = get_show();
+        show.insert_present(ColorSwapEffect::new(60.0, "pars".into(), false, false));
+        let warm = Attribute::Custom("warm_white".to_string());
+
+        show.programmer.select(vec![1, 2, 8]);
+        let selection = show.programmer.selection().to_vec();
+        // The moving head has no warm white
+        assert_eq!(program(&mut show, &selection, &warm, 40000), 2);
+        for _ in 0..8 {
+            render(
+                &mut show,
+                Duration::from_millis(250),
+                &[],
+                0,
+                Instant::now(),
+            );
+            assert_eq!(warm_white(&show, 1), 40000 / 257 * 257);
+            assert_eq!(warm_white(&show, 2), 40000 / 257 * 257);
+        }
+
+        assert_eq!(release(&mut show, &[1], None), 1);
+        assert_eq!(show.programmer.values().len(), 1);
+        // Only fixture 1 is back under the color swap, which switches every beat
+        let mut seen = Vec::new();
+        for _ in 0..8 {
+            render(
+                &mut show,
+                Duration::from_millis(250),
+                &[],
+                0,
+                Instant::now(),
+            );
+            seen.push(warm_white(&show, 1));
+            assert_eq!(warm_white(&show, 2), 40000 / 257 * 257);
+        }
+        assert!(seen.iter().any(|&v| v != 40000 / 257 * 257));
+
+        assert_eq!(clear(&mut show), 1);
+        assert!(show.programmer.is_empty());
+        assert!(show.programmer.selection().is_empty());
+    }
+
+    #[test]
+    fn release_restores_values_nothing_else_drives() {
+        let mut show = get_show();
+        let before = show
+            .get_fixture_by_id(8)
+            .unwrap()
+            .get_attribute(&Attribute::Pan)
+            .unwrap();
+
+        program(&mut show, &[8], &Attribute::Pan, 1234);
+        program(&mut show, &[8], &Attribute::Pan, 4321);
+        render(&mut show, Duration::from_millis(20), &[], 0, Instant::now());
+        let head = show.get_fixture_by_id(8).unwrap();
+        assert_eq!(head.get_attribute(&Attribute::Pan), Some(4321));
+
+        release(&mut show, &[8], Some(&[Attribute::Pan]));
+        render(&mut show, Duration::from_millis(20), &[], 0, Instant::now());
+        let head = show.get_fixture_by_id(8).unwrap();
+        assert_eq!(head.get_attribute(&Attribute::Pan), Some(before));
+    }
+}
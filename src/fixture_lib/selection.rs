@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+use crate::lib::show::Show;
+
+/// The fixtures an effect or command acts on, either listed by id or by the
+/// name of a group from the show.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Selection {
+    Fixtures(Vec<u8>),
+    Group(String),
+}
+
+impl Selection {
+    /// Ids of the selected fixtures in selection order. Ids that are not
+    /// patched and unknown groups select nothing.
+    pub fn fixture_ids(&self, show: &Show) -> Vec<u8> {
+        let ids = match self {
+            Selection::Fixtures(ids) => ids.as_slice(),
+            Selection::Group(name) => show.groups.get(name).map_or(&[][..], |ids| ids.as_slice()),
+        };
+        ids.iter()
+            .copied()
+            .filter(|&id| show.get_fixture_by_id(id).is_some())
+            .collect()
+    }
+
+    /// The name of the group if this selects one `show` doesn't define.
+    pub fn unknown_group(&self, show: &Show) -> Option<&str> {
+        match self {
+            Selection::Group(name) if !show.groups.contains_key(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Selection {
+    fn from(group: &str) -> Selection {
+        Selection::Group(group.to_string())
+    }
+}
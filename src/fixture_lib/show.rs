@@ -0,0 +1,245 @@
+use std::{collections::BTreeMap, time::Instant};
+
+use crate::lib::{
+    cue::CueList,
+    fixture::Fixture,
+    input::{InputMerge, UniverseInputStatus},
+    preset::PresetStore,
+    programmer::Programmer,
+    universe::{PatchError, Universe},
+};
+
+/// Everything that is running: all patched universes plus the effects and
+/// master values that act across them.
+pub struct Show {
+    /// Sorted by universe number.
+    pub universes: Vec<Universe>,
+    pub effects: Vec<crate::effect::RunningEffect>,
+    pub global_dimmer: u8,
+    /// DMX received from other consoles, merged into the output.
+    pub inputs: InputMerge,
+    /// Named lists of fixture ids, see `Selection`.
+    pub groups: BTreeMap<String, Vec<u8>>,
+    pub presets: PresetStore,
+    /// Played back by the render loop underneath the effects.
+    pub cues: CueList,
+    /// Applied on top of the cues and effects.
+    pub programmer: Programmer,
+    /// Numbered playbacks with faders.
+    pub executors: BTreeMap<u32, crate::executor::Executor>,
+}
+
+impl Show {
+    pub fn new() -> Show {
+        Show {
+            universes: Vec::new(),
+            effects: Vec::new(),
+            global_dimmer: 255,
+            inputs: InputMerge::default(),
+            groups: BTreeMap::new(),
+            presets: PresetStore::default(),
+            cues: CueList::default(),
+            programmer: Programmer::default(),
+            executors: BTreeMap::new(),
+        }
+    }
+
+    pub fn universe(&self, number: u16) -> Option<&Universe> {
+        self.universes.iter().find(|u| u.number == number)
+    }
+
+    /// Numbers of all universes with fixtures patched, in ascending order.
+    pub fn universe_numbers(&self) -> Vec<u16> {
+        self.universes.iter().map(|u| u.number).collect()
+    }
+
+    pub fn fixtures(&self) -> impl Iterator<Item = &Fixture> {
+        self.universes.iter().flat_map(|u| u.fixtures.iter())
+    }
+
+    pub fn fixtures_mut(&mut self) -> impl Iterator<Item = &mut Fixture> {
+        self.universes
+            .iter_mut()
+            .flat_map(|u| u.fixtures.iter_mut())
+    }
+
+    pub fn get_fixture_by_id(&self, id: u8) -> Option<&Fixture> {
+        self.fixtures().find(|fixture| fixture.id == id)
+    }
+
+    pub fn get_fixture_by_id_mut(&mut self, id: u8) -> Option<&mut Fixture> {
+        self.fixtures_mut().find(|fixture| fixture.id == id)
+    }
+
+    /// DMX frame of a universe merged with the external input still alive at
+    /// `now`, all zero if nothing is patched or received.
+    pub fn get_dmx_values(&self, universe: u16, now: Instant) -> [u8; 512] {
+        self.inputs
+            .merge(universe, &self.local_dmx_values(universe), now)
+    }
+
+    /// DMX frame of a universe as set by this show alone.
+    pub fn local_dmx_values(&self, universe: u16) -> [u8; 512] {
+        self.universe(universe)
+            .map(|u| u.get_dmx_values())
+            .unwrap_or([0; 512])
+    }
+
+    /// Sources and channel owners of every universe that receives input.
+    pub fn input_status(&self) -> Vec<UniverseInputStatus> {
+        let now = Instant::now();
+        self.inputs
+            .universe_numbers()
+            .into_iter()
+            .map(|universe| {
+                self.inputs
+                    .status(universe, &self.local_dmx_values(universe), now)
+            })
+            .collect()
+    }
+
+    /// Patch `fixture` into the universe it names, creating the universe on
+    /// first use. Fixture ids are unique across the whole show.
+    pub fn add_fixture(&mut self, fixture: Fixture) -> Result<(), PatchError> {
+        if fixture.universe == 0 {
+            return Err(PatchError::InvalidUniverse {
+                fixture_id: fixture.id,
+                universe: fixture.universe,
+            });
+        }
+        if self.get_fixture_by_id(fixture.id).is_some() {
+            return Err(PatchError::DuplicateId(fixture.id));
+        }
+
+        let index = match self
+            .universes
+            .binary_search_by_key(&fixture.universe, |u| u.number)
+        {
+            Ok(index) => index,
+            Err(index) => {
+                self.universes
+                    .insert(index, Universe::new(fixture.universe));
+                index
+            }
+        };
+        let result = self.universes[index].add_fixture(fixture);
+        if self.universes[index].fixtures.is_empty() {
+            self.universes.remove(index);
+        }
+        result
+    }
+
+    pub fn insert_present<P: crate::effect::Effect + Send + 'static>(&mut self, present: P) {
+        self.check_effect_target(&present);
+        self.effects.push(crate::effect::RunningEffect {
+            effect: Box::new(present),
+            activated: crate::merge::activation(),
+        });
+    }
+
+    /// Warn when `effect` targets a group the show doesn't define, as it
+    /// would run without selecting any fixture.
+    pub fn check_effect_target(&self, effect: &dyn crate::effect::Effect) {
+        if let Some(group) = effect
+            .target()
+            .and_then(|target| target.unknown_group(self))
+        {
+            eprintln!(
+                "⚠️ Effect targets group '{}', which the show doesn't define",
+                group
+            );
+        }
+    }
+}
+
+impl std::fmt::Debug for Show {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Show")
+            .field("universes", &self.universes)
+            .field("presents_len", &self.effects.len())
+            .field("global_dimmer", &self.global_dimmer)
+            .field("inputs", &self.inputs)
+            .field("groups", &self.groups)
+            .field("presets", &self.presets)
+            .field("cues", &self.cues)
+            .field("programmer", &self.programmer)
+            .field("executors", &self.executors.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Clone for Show {
+    fn clone(&self) -> Self {
+        Show {
+            universes: self.universes.clone(),
+            effects: Vec::new(),
+            global_dimmer: self.global_dimmer,
+            inputs: self.inputs.clone(),
+            groups: self.groups.clone(),
+            presets: self.presets.clone(),
+            cues: self.cues.clone(),
+            programmer: self.programmer.clone(),
+            executors: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::fixture::{Dimmer, FixtureComponent};
+
+    /// A one channel dimmer at `intensity`.
+    fn dimmer(id: u8, universe: u16, dmx_address: u16, intensity: u8) -> Fixture {
+        let mut fixture = Fixture::new(id, dmx_address, format!("Dimmer {}", id));
+        fixture.universe = universe;
+        fixture.add_component(FixtureComponent::Dimmer(Dimmer {
+            local: intensity,
+            intensity,
+        }));
+        fixture
+    }
+
+    #[test]
+    fn add_fixture_creates_universes_on_demand() {
+        let mut show = Show::new();
+        show.add_fixture(dimmer(1, 3, 1, 255)).unwrap();
+        show.add_fixture(dimmer(2, 1, 1, 255)).unwrap();
+        show.add_fixture(dimmer(3, 3, 2, 255)).unwrap();
+        assert_eq!(show.universe_numbers(), [1, 3]);
+        assert_eq!(show.universe(3).unwrap().fixtures.len(), 2);
+
+        assert_eq!(
+            show.add_fixture(dimmer(1, 2, 1, 255)),
+            Err(PatchError::DuplicateId(1))
+        );
+        assert!(matches!(
+            show.add_fixture(dimmer(4, 0, 1, 255)),
+            Err(PatchError::InvalidUniverse { .. })
+        ));
+        assert!(matches!(
+            show.add_fixture(dimmer(5, 4, 600, 255)),
+            Err(PatchError::InvalidAddress { .. })
+        ));
+        // A failed patch doesn't leave an empty universe behind
+        assert_eq!(show.universe_numbers(), [1, 3]);
+    }
+
+    #[test]
+    fn dmx_values_are_kept_per_universe() {
+        let mut show = Show::new();
+        show.add_fixture(dimmer(1, 1, 1, 100)).unwrap();
+        show.add_fixture(dimmer(2, 2, 1, 200)).unwrap();
+        show.add_fixture(dimmer(3, 2, 10, 50)).unwrap();
+        let now = Instant::now();
+
+        let universe1 = show.get_dmx_values(1, now);
+        assert_eq!(universe1[0], 100);
+        // Fixture 3 is at address 10 of universe 2 only
+        assert!(universe1[1..].iter().all(|&v| v == 0));
+
+        let universe2 = show.get_dmx_values(2, now);
+        assert_eq!((universe2[0], universe2[9]), (200, 50));
+        assert_eq!(show.get_dmx_values(5, now), [0; 512]);
+    }
+}
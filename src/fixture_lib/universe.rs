@@ -1,77 +1,280 @@
-use serde::{Deserialize, Serialize};
+use std::fmt;
 
 use crate::lib::fixture::Fixture;
 
+pub const DMX_CHANNELS: u16 = 512;
+
+/// Channels `first..=last` of the universe used by fixture `fixture_id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchConflict {
+    pub fixture_id: u8,
+    pub first_channel: u16,
+    pub last_channel: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// The start address is not within 1..=512.
+    InvalidAddress {
+        fixture_id: u8,
+        dmx_address: u16,
+    },
+    /// The fixture starts in the universe but its footprint runs past channel 512.
+    OutOfRange {
+        fixture_id: u8,
+        first_channel: u16,
+        last_channel: u32,
+    },
+    /// The fixture shares channels with already patched fixtures.
+    Overlap {
+        fixture_id: u8,
+        conflicts: Vec<PatchConflict>,
+    },
+    DuplicateId(u8),
+    /// Logical universes start at 1.
+    InvalidUniverse {
+        fixture_id: u8,
+        universe: u16,
+    },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::InvalidAddress {
+                fixture_id,
+                dmx_address,
+            } => write!(
+                f,
+                "fixture {} has DMX address {}, valid addresses are 1-{}",
+                fixture_id, dmx_address, DMX_CHANNELS
+            ),
+            PatchError::OutOfRange {
+                fixture_id,
+                first_channel,
+                last_channel,
+            } => write!(
+                f,
+                "fixture {} uses channels {}-{}, past the end of the universe",
+                fixture_id, first_channel, last_channel
+            ),
+            PatchError::Overlap {
+                fixture_id,
+                conflicts,
+            } => {
+                write!(f, "fixture {} overlaps", fixture_id)?;
+                for (i, conflict) in conflicts.iter().enumerate() {
+                    let separator = if i == 0 { " " } else { ", " };
+                    write!(
+                        f,
+                        "{}fixture {} on channels {}-{}",
+                        separator,
+                        conflict.fixture_id,
+                        conflict.first_channel,
+                        conflict.last_channel
+                    )?;
+                }
+                Ok(())
+            }
+            PatchError::DuplicateId(id) => write!(f, "fixture id {} is patched twice", id),
+            PatchError::InvalidUniverse {
+                fixture_id,
+                universe,
+            } => write!(
+                f,
+                "fixture {} is patched to universe {}, universes start at 1",
+                fixture_id, universe
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// One 512-channel DMX universe and the fixtures patched into it.
+#[derive(Debug, Clone)]
 pub struct Universe {
+    /// Logical universe number (1-based).
+    pub number: u16,
     pub fixtures: Vec<Fixture>,
-    pub effects: Vec<Box<dyn crate::effect::Effect + Send>>,
-    pub global_dimmer: u8,
 }
 
 impl Universe {
-    pub fn new() -> Universe {
+    pub fn new(number: u16) -> Universe {
         Universe {
+            number,
             fixtures: Vec::new(),
-            effects: Vec::new(),
-            global_dimmer: 255,
         }
     }
 
     pub fn get_fixture_by_id(&self, id: u8) -> Option<&Fixture> {
-        for fixture in &self.fixtures {
-            if fixture.id == id {
-                return Some(&fixture);
-            }
-        }
-        None
-    }
-
-    pub fn get_fixture_by_id_mut(&mut self, id: u8) -> Option<&mut Fixture> {
-        for fixture in &mut self.fixtures {
-            if fixture.id == id {
-                return Some(fixture);
-            }
-        }
-        None
+        self.fixtures.iter().find(|fixture| fixture.id == id)
     }
 
     pub fn get_dmx_values(&self) -> [u8; 512] {
         let mut dmx_values = [0u8; 512];
         for fixture in &self.fixtures {
+            // add_fixture validates the patch, but fixtures is public, so
+            // anything that doesn't fit is clipped instead of panicking
+            if fixture.dmx_address == 0 || fixture.dmx_address > DMX_CHANNELS {
+                continue;
+            }
+            let start = fixture.dmx_address as usize - 1;
             let fixture_values = fixture.get_dmx_values();
-            dmx_values[(fixture.dmx_address - 1) as usize
-                ..(fixture.dmx_address as usize - 1 + fixture_values.len())]
-                .copy_from_slice(&fixture_values);
+            let len = fixture_values.len().min(dmx_values.len() - start);
+            dmx_values[start..start + len].copy_from_slice(&fixture_values[..len]);
         }
         //println!("DMX Values: {:?}", &dmx_values[300..310]);
-        return dmx_values;
+        dmx_values
     }
 
-    pub fn add_fixture(&mut self, fixture: Fixture) {
-        self.fixtures.push(fixture);
+    /// Check that `fixture` fits into the universe next to the fixtures that
+    /// are already patched.
+    pub fn validate_fixture(&self, fixture: &Fixture) -> Result<(), PatchError> {
+        if fixture.dmx_address == 0 || fixture.dmx_address > DMX_CHANNELS {
+            return Err(PatchError::InvalidAddress {
+                fixture_id: fixture.id,
+                dmx_address: fixture.dmx_address,
+            });
+        }
+        let (first, last) = channel_range(fixture);
+        if last > DMX_CHANNELS as u32 {
+            return Err(PatchError::OutOfRange {
+                fixture_id: fixture.id,
+                first_channel: fixture.dmx_address,
+                last_channel: last,
+            });
+        }
+        if self.get_fixture_by_id(fixture.id).is_some() {
+            return Err(PatchError::DuplicateId(fixture.id));
+        }
+
+        let conflicts: Vec<PatchConflict> = self
+            .fixtures
+            .iter()
+            .filter_map(|other| {
+                let (other_first, other_last) = channel_range(other);
+                let overlap_first = first.max(other_first);
+                let overlap_last = last.min(other_last);
+                (overlap_first <= overlap_last).then_some(PatchConflict {
+                    fixture_id: other.id,
+                    first_channel: overlap_first as u16,
+                    last_channel: overlap_last as u16,
+                })
+            })
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(PatchError::Overlap {
+                fixture_id: fixture.id,
+                conflicts,
+            });
+        }
+        Ok(())
     }
 
-    pub fn insert_present<P: crate::effect::Effect + Send + 'static>(&mut self, present: P) {
-        self.effects.push(Box::new(present));
+    pub fn add_fixture(&mut self, fixture: Fixture) -> Result<(), PatchError> {
+        self.validate_fixture(&fixture)?;
+        self.fixtures.push(fixture);
+        Ok(())
     }
 }
 
-impl std::fmt::Debug for Universe {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Universe")
-            .field("fixtures", &self.fixtures)
-            .field("presents_len", &self.effects.len())
-            .field("global_dimmer", &self.global_dimmer)
-            .finish()
-    }
+/// First and last DMX channel used by `fixture`. Fixtures without channels
+/// have an empty range.
+fn channel_range(fixture: &Fixture) -> (u32, u32) {
+    let first = fixture.dmx_address as u32;
+    (
+        first,
+        (first + fixture.footprint() as u32).saturating_sub(1),
+    )
 }
 
-impl Clone for Universe {
-    fn clone(&self) -> Self {
-        Universe {
-            fixtures: self.fixtures.clone(),
-            effects: Vec::new(),
-            global_dimmer: self.global_dimmer,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::fixture::FixtureComponent;
+
+    /// Fixture `id` using `channels` channels from `dmx_address`.
+    fn fixture(id: u8, dmx_address: u16, channels: usize) -> Fixture {
+        let mut fixture = Fixture::new(id, dmx_address, format!("Fixture {}", id));
+        fixture.components = vec![FixtureComponent::Zero; channels];
+        fixture
+    }
+
+    #[test]
+    fn rejects_addresses_outside_the_universe() {
+        let universe = Universe::new(1);
+        for dmx_address in [0, 513] {
+            assert_eq!(
+                universe.validate_fixture(&fixture(1, dmx_address, 1)),
+                Err(PatchError::InvalidAddress {
+                    fixture_id: 1,
+                    dmx_address
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_footprints_running_past_512() {
+        let mut universe = Universe::new(1);
+        assert_eq!(
+            universe.add_fixture(fixture(1, 510, 4)),
+            Err(PatchError::OutOfRange {
+                fixture_id: 1,
+                first_channel: 510,
+                last_channel: 513,
+            })
+        );
+        // Ending on the last channel fits
+        assert_eq!(universe.add_fixture(fixture(1, 509, 4)), Ok(()));
+    }
+
+    #[test]
+    fn rejects_overlapping_fixtures() {
+        let mut universe = Universe::new(1);
+        universe.add_fixture(fixture(1, 1, 6)).unwrap();
+        universe.add_fixture(fixture(2, 11, 6)).unwrap();
+
+        assert_eq!(
+            universe.add_fixture(fixture(3, 5, 8)),
+            Err(PatchError::Overlap {
+                fixture_id: 3,
+                conflicts: vec![
+                    PatchConflict {
+                        fixture_id: 1,
+                        first_channel: 5,
+                        last_channel: 6,
+                    },
+                    PatchConflict {
+                        fixture_id: 2,
+                        first_channel: 11,
+                        last_channel: 12,
+                    },
+                ],
+            })
+        );
+        assert_eq!(universe.fixtures.len(), 2);
+    }
+
+    #[test]
+    fn rejects_duplicate_ids() {
+        let mut universe = Universe::new(1);
+        universe.add_fixture(fixture(1, 1, 6)).unwrap();
+        assert_eq!(
+            universe.add_fixture(fixture(1, 100, 6)),
+            Err(PatchError::DuplicateId(1))
+        );
+    }
+
+    #[test]
+    fn accepts_adjacent_fixtures() {
+        let mut universe = Universe::new(1);
+        for (id, dmx_address) in [(1, 1), (2, 7), (3, 13), (4, 507)] {
+            assert_eq!(universe.add_fixture(fixture(id, dmx_address, 6)), Ok(()));
         }
+        // Fixtures without channels take no room
+        assert_eq!(universe.add_fixture(fixture(5, 7, 0)), Ok(()));
+        assert_eq!(universe.fixtures.len(), 5);
     }
 }
@@ -1,21 +1,45 @@
 use std::{
+    path::Path,
     sync::{Arc, Mutex},
     time,
 };
 
 use futures::{SinkExt, StreamExt};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use warp::{Filter, filters::ws::Message};
 
-use crate::{lib::fixture::FixtureComponent, patching::get_universe};
+use crate::{
+    clock::SystemClock,
+    effect::EffectCommand,
+    executor::ExecutorCommand,
+    lib::{
+        cue::CueCommand,
+        fixture::{Attribute, FixtureComponent},
+        preset::{Preset, PresetStore},
+        programmer::{self, ProgrammerCommand, RecordTarget},
+        selection::Selection,
+        show::Show,
+    },
+    output::OutputManager,
+    patching::get_show,
+    render::FrameBuffer,
+    show_file::ShowFile,
+};
 
 mod artnet;
+mod clock;
 mod dmx;
 mod effect;
+mod executor;
 #[path = "fixture_lib/lib.rs"]
 mod lib;
+mod merge;
+mod output;
 mod patching;
+mod render;
+mod sacn;
+mod show_file;
 
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
@@ -24,15 +48,53 @@ enum IncomingEvent {
         event: String,
         state: String,
     },
+    /// Recall a preset, fading over `fade_ms`.
     Preset {
         event: String,
         number: String,
+        #[serde(default)]
+        fade_ms: u64,
+    },
+    /// Store the current values, optionally only of some fixtures or
+    /// attributes, as preset number `store`.
+    StorePreset {
+        event: String,
+        store: u32,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        selection: Option<Selection>,
+        #[serde(default)]
+        attributes: Option<Vec<Attribute>>,
+    },
+    /// Select, set, release, clear or record, e.g. `"programmer": "clear"` or
+    /// `"programmer": { "set": { "attribute": "dimmer", "value": 65535 } }`.
+    Programmer {
+        event: String,
+        programmer: ProgrammerCommand,
+    },
+    /// Drive the cue list, e.g. `"cue": "go"` or `"cue": { "goto": 3 }`.
+    Cue {
+        event: String,
+        cue: CueCommand,
+    },
+    /// Drive executor `executor`, e.g. `"command": "go"` or
+    /// `"command": { "level": 128 }`.
+    Executor {
+        event: String,
+        executor: u32,
+        command: ExecutorCommand,
+    },
+    /// Start or stop effects, e.g. `"effect": "stop"`.
+    Effect {
+        event: String,
+        effect: EffectCommand,
     },
     InputValues {
         event: String,
         input1: String,
         input2: String,
-    }, // <-- new variant
+    },
     Color {
         event: String,
         color: String,
@@ -103,47 +165,272 @@ enum IncomingEvent {
     },
 }
 
+impl IncomingEvent {
+    /// The name the UI sent the message under, e.g. `"pan-1"`. Variants are
+    /// told apart by their payload fields, so this is only echoed back.
+    fn event(&self) -> &str {
+        match self {
+            IncomingEvent::Strobo { event, .. }
+            | IncomingEvent::Preset { event, .. }
+            | IncomingEvent::StorePreset { event, .. }
+            | IncomingEvent::Programmer { event, .. }
+            | IncomingEvent::Cue { event, .. }
+            | IncomingEvent::Executor { event, .. }
+            | IncomingEvent::Effect { event, .. }
+            | IncomingEvent::InputValues { event, .. }
+            | IncomingEvent::Color { event, .. }
+            | IncomingEvent::Smooth { event, .. }
+            | IncomingEvent::Offset { event, .. }
+            | IncomingEvent::Bpm { event, .. }
+            | IncomingEvent::Pan1 { event, .. }
+            | IncomingEvent::Tilt1 { event, .. }
+            | IncomingEvent::Pan2 { event, .. }
+            | IncomingEvent::Tilt2 { event, .. }
+            | IncomingEvent::Color1 { event, .. }
+            | IncomingEvent::Color2 { event, .. }
+            | IncomingEvent::Gobo1 { event, .. }
+            | IncomingEvent::Gobo2 { event, .. }
+            | IncomingEvent::Focus1 { event, .. }
+            | IncomingEvent::Focus2 { event, .. }
+            | IncomingEvent::Dimmer1 { event, .. }
+            | IncomingEvent::Dimmer2 { event, .. }
+            | IncomingEvent::GlobalDimmer { event, .. } => event,
+        }
+    }
+}
+
+/// Reply of `/api/dmx/<universe>`.
+#[derive(Serialize)]
+struct DmxReply {
+    universe: u16,
+    tick: u64,
+    values: Vec<u8>,
+}
+
+/// Frames rendered per second, the most a DMX line can carry.
+const RENDER_RATE: f64 = 44.0;
+
 #[tokio::main]
 async fn main() {
-    let universe = Arc::new(Mutex::new(get_universe()));
-    artnet::launch_artnet_send_thread(universe.clone());
-    //dmx::launch_dmx_send_thread(universe.clone());
-    effect::launch_present_thread(universe.clone(), time::Duration::from_millis(100));
-
-    // Add a color swap effect for the PAR fixtures at 480 BPM (8 changes per second)
-    universe
-        .lock()
-        .unwrap()
-        .insert_present(effect::ColorSwapEffect::new(
-            40.0,  // 120 BPM (2 beats per second)
-            7,     // 7 fixtures
-            true,  // Enable offset pattern - different starting colors
-            false, // Disable smooth transitions for testing
-        ));
-
-    let universe_filter = warp::any().map(move || universe.clone());
-
-    let ws_route = warp::path("ws").and(warp::ws()).and(universe_filter).map(
-        |ws: warp::ws::Ws, universe: Arc<Mutex<lib::universe::Universe>>| {
-            ws.on_upgrade(move |socket| handle_websocket(socket, universe.clone()))
+    let (mut show, show_file) = match std::env::args().nth(1) {
+        Some(path) => {
+            match ShowFile::load(Path::new(&path))
+                .and_then(|show_file| show_file.to_show().map(|show| (show, show_file)))
+            {
+                Ok((show, show_file)) => {
+                    println!(
+                        "📂 Loaded show file {} ({} fixtures in {} universes)",
+                        path,
+                        show.fixtures().count(),
+                        show.universes.len()
+                    );
+                    (show, show_file)
+                }
+                Err(e) => {
+                    eprintln!("Failed to load show file: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            println!("📂 No show file given, using built-in demo patch");
+            let mut show = get_show();
+            // Add a color swap effect for the PAR fixtures at 480 BPM (8 changes per second)
+            show.insert_present(effect::ColorSwapEffect::new(
+                40.0,          // 120 BPM (2 beats per second)
+                "pars".into(), // the PAR group of the demo patch
+                true,          // Enable offset pattern - different starting colors
+                false,         // Disable smooth transitions for testing
+            ));
+            (show, ShowFile::default())
+        }
+    };
+    match PresetStore::load(&show_file.preset_file()) {
+        Ok(presets) => show.presets = presets,
+        Err(e) => {
+            eprintln!("Failed to load presets: {}", e);
+            std::process::exit(1);
+        }
+    }
+    let mut outputs = OutputManager::new();
+    let mut artnet_ports = Vec::new();
+    for config in show_file.artnet_outputs() {
+        match config.open(&show) {
+            Ok(output) => {
+                artnet_ports.push(output.local_port());
+                outputs.add(Box::new(output));
+            }
+            Err(e) => {
+                eprintln!("Failed to start Art-Net output: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    for config in &show_file.sacn {
+        match config.open(&show) {
+            Ok(output) => outputs.add(Box::new(output)),
+            Err(e) => {
+                eprintln!("Failed to start sACN output: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    for config in &show_file.serial {
+        outputs.add(Box::new(dmx::SerialOutput::new(config.clone())));
+    }
+
+    let show = Arc::new(Mutex::new(show));
+    let outputs = Arc::new(Mutex::new(outputs));
+    let frames = FrameBuffer::new();
+    let render_thread = render::launch_render_thread(
+        show.clone(),
+        frames.clone(),
+        outputs.clone(),
+        Arc::new(SystemClock::new()),
+        RENDER_RATE,
+    );
+    if let Some(input) = show_file.sacn_input.clone() {
+        let own_cids = show_file
+            .sacn
+            .iter()
+            .filter_map(|output| output.cid().ok())
+            .collect();
+        if let Err(e) = sacn::launch_sacn_receive_thread(show.clone(), input, own_cids) {
+            eprintln!("Failed to start sACN input: {}", e);
+            std::process::exit(1);
+        }
+    }
+    let nodes = Arc::new(Mutex::new(artnet::NodeList::default()));
+    if (show_file.artnet_discovery.enabled || show_file.artnet_input.is_some())
+        && let Err(e) = artnet::launch_artnet_receive_thread(
+            show.clone(),
+            show_file.artnet_discovery.clone(),
+            show_file.artnet_input.clone(),
+            nodes.clone(),
+            artnet_ports,
+        )
+    {
+        if show_file.artnet_input.is_some() {
+            eprintln!("Failed to start Art-Net input: {}", e);
+            std::process::exit(1);
+        }
+        eprintln!("⚠️ Art-Net discovery disabled: {}", e);
+    }
+
+    let inputs_show = show.clone();
+    let presets_show = show.clone();
+    let cues_show = show.clone();
+    let executors_show = show.clone();
+    let programmer_show = show.clone();
+    let cue_command_show = show.clone();
+    let cue_goto_show = show.clone();
+    let show_filter = warp::any().map(move || show.clone());
+
+    let ws_route = warp::path("ws").and(warp::ws()).and(show_filter).map(
+        |ws: warp::ws::Ws, show: Arc<Mutex<Show>>| {
+            ws.on_upgrade(move |socket| handle_websocket(socket, show.clone()))
         },
     );
 
+    let nodes_route = warp::path!("api" / "nodes")
+        .and(warp::get())
+        .map(move || warp::reply::json(&nodes.lock().unwrap().nodes()));
+
+    let inputs_route = warp::path!("api" / "inputs")
+        .and(warp::get())
+        .map(move || warp::reply::json(&inputs_show.lock().unwrap().input_status()));
+
+    let outputs_route = warp::path!("api" / "outputs")
+        .and(warp::get())
+        .map(move || warp::reply::json(&outputs.lock().unwrap().status()));
+
+    let presets_route = warp::path!("api" / "presets")
+        .and(warp::get())
+        .map(move || warp::reply::json(presets_show.lock().unwrap().presets.presets()));
+
+    let programmer_route = warp::path!("api" / "programmer")
+        .and(warp::get())
+        .map(move || warp::reply::json(&programmer_show.lock().unwrap().programmer.status()));
+
+    let cues_route = warp::path!("api" / "cues")
+        .and(warp::get())
+        .map(move || warp::reply::json(&cues_show.lock().unwrap().cues.status()));
+
+    // POST /api/cues/go, /back or /pause, replying with the new status
+    let cue_command_route = warp::path!("api" / "cues" / String)
+        .and(warp::post())
+        .and_then(move |command: String| {
+            let show = cue_command_show.clone();
+            async move {
+                let command = match command.as_str() {
+                    "go" => CueCommand::Go,
+                    "back" => CueCommand::Back,
+                    "pause" => CueCommand::Pause,
+                    _ => return Err(warp::reject::not_found()),
+                };
+                run_cue_command(command, &show);
+                let status = show.lock().unwrap().cues.status();
+                Ok(warp::reply::json(&status))
+            }
+        });
+
+    let cue_goto_route = warp::path!("api" / "cues" / "goto" / u32)
+        .and(warp::post())
+        .map(move |number| {
+            run_cue_command(CueCommand::Goto(number), &cue_goto_show);
+            warp::reply::json(&cue_goto_show.lock().unwrap().cues.status())
+        });
+
+    let executors_route = warp::path!("api" / "executors")
+        .and(warp::get())
+        .map(move || warp::reply::json(&executor::status(&executors_show.lock().unwrap())));
+
+    // Served from the last rendered frame, without locking the show
+    let dmx_route = warp::path!("api" / "dmx" / u16)
+        .and(warp::get())
+        .map(move |universe| {
+            let frame = frames.latest();
+            warp::reply::json(&DmxReply {
+                universe,
+                tick: frame.tick,
+                values: frame.universe(universe).to_vec(),
+            })
+        });
+
     let static_files = warp::fs::dir("static/");
 
     println!("Server running on http://127.0.0.1:3030");
-    warp::serve(ws_route.or(static_files))
-        .run(([0, 0, 0, 0], 3030))
-        .await;
+    let server = warp::serve(
+        ws_route
+            .or(nodes_route)
+            .or(inputs_route)
+            .or(outputs_route)
+            .or(presets_route)
+            .or(programmer_route)
+            .or(cues_route)
+            .or(cue_goto_route)
+            .or(executors_route)
+            .or(cue_command_route)
+            .or(dmx_route)
+            .or(static_files),
+    )
+    .run(([0, 0, 0, 0], 3030));
+    tokio::select! {
+        _ = server => {}
+        _ = tokio::signal::ctrl_c() => println!("🛑 Shutting down"),
+    }
+
+    // Let sACN receivers release our universes instead of waiting for the timeout
+    render_thread.stop();
 }
 
-async fn handle_websocket(ws: warp::ws::WebSocket, universe: Arc<Mutex<lib::universe::Universe>>) {
+async fn handle_websocket(ws: warp::ws::WebSocket, show: Arc<Mutex<Show>>) {
     let (mut ws_tx, mut ws_rx) = ws.split();
 
     while let Some(result) = ws_rx.next().await {
         match result {
             Ok(msg) if msg.is_text() => {
-                if let Err(e) = handle_text_message(&msg, &universe, &mut ws_tx).await {
+                if let Err(e) = handle_text_message(&msg, &show, &mut ws_tx).await {
                     eprintln!("Error handling message: {}", e);
                     break;
                 }
@@ -161,17 +448,46 @@ async fn handle_websocket(ws: warp::ws::WebSocket, universe: Arc<Mutex<lib::univ
 
 async fn handle_text_message(
     msg: &Message,
-    universe: &Arc<Mutex<lib::universe::Universe>>,
+    show: &Arc<Mutex<Show>>,
     ws_tx: &mut futures::stream::SplitSink<warp::ws::WebSocket, Message>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let text = msg.to_str().unwrap();
     let event = serde_json::from_str::<IncomingEvent>(text)?;
+    let reply = format!("ok {}", event.event());
 
     match event {
-        IncomingEvent::Strobo { state, .. } => handle_strobo(state, universe),
-        IncomingEvent::Preset { number, .. } => {
-            println!("🔢 Preset button pressed: {}", number);
+        IncomingEvent::Strobo { state, .. } => handle_strobo(state, show),
+        IncomingEvent::Preset {
+            number, fade_ms, ..
+        } => match number.trim().parse::<u32>() {
+            Ok(number) => recall_preset(number, fade_ms, show),
+            Err(_) => eprintln!("Invalid preset number '{}'", number),
+        },
+        IncomingEvent::StorePreset {
+            store,
+            name,
+            selection,
+            attributes,
+            ..
+        } => {
+            let mut show = show.lock().unwrap();
+            let mut preset = Preset::capture(&show, selection.as_ref(), attributes.as_deref());
+            preset.name = name;
+            println!(
+                "💾 Preset {} stored ({} values)",
+                store,
+                preset.values.len()
+            );
+            if let Err(e) = show.presets.store(store, preset) {
+                eprintln!("Failed to save presets: {}", e);
+            }
         }
+        IncomingEvent::Programmer { programmer, .. } => run_programmer_command(programmer, show),
+        IncomingEvent::Cue { cue, .. } => run_cue_command(cue, show),
+        IncomingEvent::Executor {
+            executor, command, ..
+        } => run_executor_command(executor, command, show),
+        IncomingEvent::Effect { effect, .. } => run_effect_command(effect, show),
         IncomingEvent::InputValues { input1, input2, .. } => {
             println!(
                 "📝 Inputs received: input1='{}', input2='{}'",
@@ -188,239 +504,95 @@ async fn handle_text_message(
         },
         IncomingEvent::Smooth { smooth, .. } => {
             println!("🔄 Smooth toggle: {}", smooth);
-            universe
-                .lock()
-                .unwrap()
-                .effects
-                .iter_mut()
-                .for_each(|present| {
-                    if let Some(color_swap) = present
-                        .as_mut()
-                        .as_any_mut()
-                        .downcast_mut::<effect::ColorSwapEffect>()
-                    {
-                        color_swap.smooth = smooth;
-                    }
-                });
+            show.lock().unwrap().effects.iter_mut().for_each(|present| {
+                if let Some(color_swap) = present
+                    .effect
+                    .as_any_mut()
+                    .downcast_mut::<effect::ColorSwapEffect>()
+                {
+                    color_swap.smooth = smooth;
+                }
+            });
         }
         IncomingEvent::Offset { offset, .. } => {
             println!("🔀 Offset toggle: {}", offset);
-            universe
-                .lock()
-                .unwrap()
-                .effects
-                .iter_mut()
-                .for_each(|present| {
-                    if let Some(color_swap) = present
-                        .as_mut()
-                        .as_any_mut()
-                        .downcast_mut::<effect::ColorSwapEffect>()
-                    {
-                        color_swap.set_offset_pattern(offset);
-                    }
-                });
+            show.lock().unwrap().effects.iter_mut().for_each(|present| {
+                if let Some(color_swap) = present
+                    .effect
+                    .as_any_mut()
+                    .downcast_mut::<effect::ColorSwapEffect>()
+                {
+                    color_swap.set_offset_pattern(offset);
+                }
+            });
         }
         IncomingEvent::Bpm { bpm, .. } => {
             println!("⏱️ BPM set to: {}", bpm);
-            universe
-                .lock()
-                .unwrap()
-                .effects
-                .iter_mut()
-                .for_each(|present| {
-                    if let Some(color_swap) = present
-                        .as_mut()
-                        .as_any_mut()
-                        .downcast_mut::<effect::ColorSwapEffect>()
-                    {
-                        color_swap.bpm = bpm as f32;
-                    }
-                });
+            show.lock().unwrap().effects.iter_mut().for_each(|present| {
+                if let Some(color_swap) = present
+                    .effect
+                    .as_any_mut()
+                    .downcast_mut::<effect::ColorSwapEffect>()
+                {
+                    color_swap.bpm = bpm as f32;
+                }
+            });
         }
         IncomingEvent::Pan1 { pan_1, .. } => {
             println!("↔️ Pan 1 set to: {}", pan_1);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(8)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::Position(pos) = component {
-                        pos.pan = pan_1;
-                    }
-                });
+            program_fixture(show, 8, Attribute::Pan, pan_1);
         }
         IncomingEvent::Tilt1 { tilt_1, .. } => {
             println!("↕️ Tilt 1 set to: {}", tilt_1);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(8)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::Position(pos) = component {
-                        pos.tilt = tilt_1;
-                    }
-                });
+            program_fixture(show, 8, Attribute::Tilt, tilt_1);
         }
         IncomingEvent::Pan2 { pan_2, .. } => {
             println!("↔️ Pan 2 set to: {}", pan_2);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(9)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::Position(pos) = component {
-                        pos.pan = pan_2;
-                    }
-                });
+            program_fixture(show, 9, Attribute::Pan, pan_2);
         }
         IncomingEvent::Tilt2 { tilt_2, .. } => {
             println!("↕️ Tilt 2 set to: {}", tilt_2);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(9)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::Position(pos) = component {
-                        pos.tilt = tilt_2;
-                    }
-                });
+            program_fixture(show, 9, Attribute::Tilt, tilt_2);
         }
         IncomingEvent::Color1 { color_1, .. } => {
             println!("🎨 Color 1 set to: {}", color_1);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(8)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::ColorWheel(color) = component {
-                        color.index = color_1;
-                    }
-                });
+            program_fixture(show, 8, Attribute::ColorWheel, color_1 as u16 * 257);
         }
         IncomingEvent::Color2 { color_2, .. } => {
             println!("🎨 Color 2 set to: {}", color_2);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(9)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::ColorWheel(color) = component {
-                        color.index = color_2;
-                    }
-                });
+            program_fixture(show, 9, Attribute::ColorWheel, color_2 as u16 * 257);
         }
         IncomingEvent::Gobo1 { gobo_1, .. } => {
             println!("💫 Gobo 1 set to: {}", gobo_1);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(8)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::Gobo(gobo) = component {
-                        gobo.index = gobo_1;
-                    }
-                });
+            program_fixture(show, 8, Attribute::Gobo, gobo_1 as u16 * 257);
         }
         IncomingEvent::Gobo2 { gobo_2, .. } => {
             println!("💫 Gobo 2 set to: {}", gobo_2);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(9)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::Gobo(gobo) = component {
-                        gobo.index = gobo_2;
-                    }
-                });
+            program_fixture(show, 9, Attribute::Gobo, gobo_2 as u16 * 257);
         }
         IncomingEvent::Focus1 { focus_1, .. } => {
             println!("🔍 Focus 1 set to: {}", focus_1);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(8)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::Focus(focus) = component {
-                        focus.value = focus_1;
-                    }
-                });
+            program_fixture(show, 8, Attribute::Focus, focus_1 as u16 * 257);
         }
         IncomingEvent::Focus2 { focus_2, .. } => {
             println!("🔍 Focus 2 set to: {}", focus_2);
-            universe
-                .lock()
-                .unwrap()
-                .get_fixture_by_id_mut(9)
-                .unwrap()
-                .components
-                .iter_mut()
-                .for_each(|component| {
-                    if let FixtureComponent::Focus(focus) = component {
-                        focus.value = focus_2;
-                    }
-                });
+            program_fixture(show, 9, Attribute::Focus, focus_2 as u16 * 257);
         }
         IncomingEvent::Dimmer1 { dimmer_1, .. } => {
             println!("💡 Dimmer 1 set to: {}", dimmer_1);
-            let mut u = universe.lock().unwrap();
-            let g = u.global_dimmer;
-            if let Some(fx) = u.get_fixture_by_id_mut(8) {
-                fx.components.iter_mut().for_each(|component| {
-                    if let FixtureComponent::Dimmer(dimmer) = component {
-                        // store local setting and compute effective intensity using global dimmer
-                        dimmer.local = dimmer_1;
-                        dimmer.intensity = ((dimmer.local as u16 * g as u16) / 255) as u8;
-                    }
-                });
-            }
+            program_fixture(show, 8, Attribute::Dimmer, dimmer_1 as u16 * 257);
         }
         IncomingEvent::Dimmer2 { dimmer_2, .. } => {
             println!("💡 Dimmer 2 set to: {}", dimmer_2);
-            let mut u = universe.lock().unwrap();
-            let g = u.global_dimmer;
-            if let Some(fx) = u.get_fixture_by_id_mut(9) {
-                fx.components.iter_mut().for_each(|component| {
-                    if let FixtureComponent::Dimmer(dimmer) = component {
-                        dimmer.local = dimmer_2;
-                        dimmer.intensity = ((dimmer.local as u16 * g as u16) / 255) as u8;
-                    }
-                });
-            }
+            program_fixture(show, 9, Attribute::Dimmer, dimmer_2 as u16 * 257);
         }
         IncomingEvent::GlobalDimmer { dimmer, .. } => {
             println!("🌐 Global dimmer set to: {}", dimmer);
-            let mut u = universe.lock().unwrap();
+            let mut u = show.lock().unwrap();
             u.global_dimmer = dimmer;
             // Recompute effective intensities for all fixtures using their local dimmer value
             let g = u.global_dimmer;
-            for fixture in u.fixtures.iter_mut() {
+            for fixture in u.fixtures_mut() {
                 for component in fixture.components.iter_mut() {
                     if let FixtureComponent::Dimmer(d) = component {
                         d.intensity = ((d.local as u16 * g as u16) / 255) as u8;
@@ -431,84 +603,126 @@ async fn handle_text_message(
     }
 
     // Echo back to client
-    ws_tx.send(Message::text("ok")).await?;
+    ws_tx.send(Message::text(reply)).await?;
     Ok(())
 }
 
-fn handle_strobo(state: String, universe: &Arc<Mutex<lib::universe::Universe>>) {
-    let intensity = if state == "down" {
-        println!("💡 Strobo PRESSED");
-        255
-    } else {
-        println!("💡 Strobo RELEASED");
-        0
+fn recall_preset(number: u32, fade_ms: u64, show: &Arc<Mutex<Show>>) {
+    let mut show = show.lock().unwrap();
+    let Some(preset) = show.presets.get(number) else {
+        println!("🔢 Preset {} is empty", number);
+        return;
     };
+    let values = preset.values.clone();
+    println!("🔢 Preset {} recalled, fading {} ms", number, fade_ms);
+    effect::start_fade(&mut show, values, time::Duration::from_millis(fade_ms));
+}
+
+fn run_cue_command(command: CueCommand, show: &Arc<Mutex<Show>>) {
+    let mut show = show.lock().unwrap();
+    if show.cues.command(command) {
+        let status = show.cues.status();
+        match (command, status.current) {
+            (CueCommand::Pause, _) if status.paused => println!("⏸️ Cue list paused"),
+            (CueCommand::Pause, _) => println!("▶️ Cue list resumed"),
+            (_, Some(current)) => println!("🎬 Cue {} ({:?})", current, command),
+            (_, None) => {}
+        }
+    } else {
+        println!("🎬 No cue to go to for {:?}", command);
+    }
+}
+
+fn run_executor_command(number: u32, command: ExecutorCommand, show: &Arc<Mutex<Show>>) {
+    let mut show = show.lock().unwrap();
+    if !executor::command(&mut show, number, command) {
+        println!("🎚️ Executor {} can't {:?}", number, command);
+        return;
+    }
+    // Fader moves come in a stream, only log the buttons
+    if !matches!(command, ExecutorCommand::Level(_)) {
+        println!("🎚️ Executor {} {:?}", number, command);
+    }
+}
+
+fn run_effect_command(command: EffectCommand, show: &Arc<Mutex<Show>>) {
+    let mut show = show.lock().unwrap();
+    let running = show.effects.len();
+    if !effect::command(&mut show, command.clone()) {
+        println!("✨ Can't start {:?}", command);
+        return;
+    }
+    match command {
+        EffectCommand::Stop => println!("✨ Stopped {} effects", running - show.effects.len()),
+        _ => println!("✨ Started {:?}", command),
+    }
+}
+
+/// Set one attribute of one fixture through the programmer, for the fixed
+/// moving head controls of the UI.
+fn program_fixture(show: &Arc<Mutex<Show>>, fixture_id: u8, attribute: Attribute, value: u16) {
+    programmer::program(&mut show.lock().unwrap(), &[fixture_id], &attribute, value);
+}
 
-    universe
-        .lock()
-        .unwrap()
-        .fixtures
-        .iter_mut()
-        .for_each(|fixture| {
-            for component in fixture.components.iter_mut() {
-                if let FixtureComponent::CustomValue(cv) = component {
-                    if cv.name == "strobe" {
-                        cv.value = intensity;
+fn run_programmer_command(command: ProgrammerCommand, show: &Arc<Mutex<Show>>) {
+    let mut show = show.lock().unwrap();
+    match command {
+        ProgrammerCommand::Select(selection) => {
+            let fixture_ids = selection.fixture_ids(&show);
+            println!("👉 Selected fixtures {:?}", fixture_ids);
+            show.programmer.select(fixture_ids);
+        }
+        ProgrammerCommand::Set { attribute, value } => {
+            let selection = show.programmer.selection().to_vec();
+            let count = programmer::program(&mut show, &selection, &attribute, value);
+            println!("🎛️ {:?} set to {} on {} fixtures", attribute, value, count);
+        }
+        ProgrammerCommand::Release => {
+            let selection = show.programmer.selection().to_vec();
+            let count = programmer::release(&mut show, &selection, None);
+            println!("🎛️ Released {} values", count);
+        }
+        ProgrammerCommand::Clear => {
+            let count = programmer::clear(&mut show);
+            println!("🎛️ Programmer cleared ({} values)", count);
+        }
+        ProgrammerCommand::Record(target) => {
+            if show.programmer.is_empty() {
+                println!("🎛️ Programmer is empty, nothing to record");
+                return;
+            }
+            let values = show.programmer.values();
+            match target {
+                RecordTarget::Preset(number) => {
+                    println!("💾 Preset {} recorded ({} values)", number, values.len());
+                    let preset = Preset {
+                        name: String::new(),
+                        values,
+                    };
+                    if let Err(e) = show.presets.store(number, preset) {
+                        eprintln!("Failed to save presets: {}", e);
                     }
                 }
+                RecordTarget::Cue(number) => {
+                    let count = show.cues.record(number, &values);
+                    println!("💾 Cue {} recorded ({} values)", number, count);
+                }
             }
-        });
-}
-
-fn degree_to_16_bit(degree: u32, range: u32) -> u32 {
-    (degree * 65535) / range
+        }
+    }
 }
 
-fn pan_tilt_sweep(
-    pan_start: u32,
-    pan_end: u32,
-    tilt_start: u32,
-    tilt_end: u32,
-    pan_speed: u32,
-    tilt_speed: u32,
-) {
-    let mut pan_current = pan_start as i32;
-    let mut tilt_current = tilt_start as i32;
-
-    let mut pan_dir = 1; // 1 = forward, -1 = backward
-    let mut tilt_dir = 1; // 1 = up, -1 = down
-
-    loop {
-        // ---- PAN ----
-        if pan_current >= pan_end as i32 {
-            pan_dir = -1;
-        } else if pan_current <= pan_start as i32 {
-            pan_dir = 1;
-        }
-
-        // ---- TILT ----
-        if tilt_current >= tilt_end as i32 {
-            tilt_dir = -1;
-        } else if tilt_current <= tilt_start as i32 {
-            tilt_dir = 1;
-        }
-
-        // ---- Convert to 16-bit DMX values ----
-        let pan_dmx = degree_to_16_bit(pan_current as u32, 540); // 540° pan range typical
-        let tilt_dmx = degree_to_16_bit(tilt_current as u32, 270); // 270° tilt range typical
-
-        // ---- Output or send to DMX ----
-        println!(
-            "Pan: {:3}° → DMX {},   Tilt: {:3}° → DMX {}",
-            pan_current, pan_dmx, tilt_current, tilt_dmx
-        );
-
-        // ---- Update positions ----
-        pan_current += pan_dir * pan_speed as i32;
-        tilt_current += tilt_dir * tilt_speed as i32;
-
-        // ---- Wait a bit ----
-        std::thread::sleep(std::time::Duration::from_millis(50));
+/// Strobe every fixture that has a strobe channel while the button is held.
+fn handle_strobo(state: String, show: &Arc<Mutex<Show>>) {
+    let strobe = Attribute::Custom("strobe".to_string());
+    let mut show = show.lock().unwrap();
+    let fixture_ids: Vec<u8> = show.fixtures().map(|f| f.id).collect();
+    if state == "down" {
+        println!("💡 Strobo PRESSED");
+        programmer::program(&mut show, &fixture_ids, &strobe, 65535);
+    } else {
+        println!("💡 Strobo RELEASED");
+        programmer::release(&mut show, &fixture_ids, Some(&[strobe]));
     }
 }
 
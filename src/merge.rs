@@ -0,0 +1,168 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::lib::{fixture::Attribute, preset::PresetValue};
+
+/// Priority of playbacks that don't set one.
+pub const DEFAULT_PRIORITY: u8 = 0;
+
+/// What one playback sets in the current frame.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub priority: u8,
+    /// When the playback was started, see `activation`.
+    pub activated: u64,
+    pub values: Vec<PresetValue>,
+}
+
+static ACTIVATIONS: AtomicU64 = AtomicU64::new(1);
+
+/// A stamp later than every stamp handed out before, taken whenever a
+/// playback starts so LTP attributes can go to the latest one.
+pub fn activation() -> u64 {
+    ACTIVATIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Whether `attribute` combines highest-takes-precedence. Intensities do,
+/// everything else goes to the latest playback.
+pub fn is_htp(attribute: &Attribute) -> bool {
+    matches!(attribute, Attribute::Dimmer)
+}
+
+/// The final value of every attribute set by any of `layers` or by hand in
+/// `programmer`.
+///
+/// Programmer values override every layer, whatever its priority. Otherwise
+/// only the layers with the highest priority setting an attribute take part.
+/// Among them intensities take the highest value and everything else the
+/// value of the layer activated last.
+pub fn merge(layers: &[Layer], programmer: &[PresetValue]) -> HashMap<(u8, Attribute), u16> {
+    // Winning (priority, activation, value) so far
+    let mut merged: HashMap<(u8, Attribute), (u8, u64, u16)> = HashMap::new();
+    for layer in layers {
+        for value in &layer.values {
+            let candidate = (layer.priority, layer.activated, value.value);
+            match merged.entry((value.fixture_id, value.attribute.clone())) {
+                Entry::Vacant(entry) => {
+                    entry.insert(candidate);
+                }
+                Entry::Occupied(mut entry) => {
+                    let (priority, activated, current) = *entry.get();
+                    let wins = if layer.priority != priority {
+                        layer.priority > priority
+                    } else if is_htp(&value.attribute) {
+                        value.value > current
+                    } else {
+                        layer.activated > activated
+                    };
+                    if wins {
+                        entry.insert(candidate);
+                    }
+                }
+            }
+        }
+    }
+    let mut merged: HashMap<(u8, Attribute), u16> = merged
+        .into_iter()
+        .map(|(key, (_, _, value))| (key, value))
+        .collect();
+    for value in programmer {
+        merged.insert((value.fixture_id, value.attribute.clone()), value.value);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(priority: u8, activated: u64, values: &[(u8, Attribute, u16)]) -> Layer {
+        Layer {
+            priority,
+            activated,
+            values: values
+                .iter()
+                .map(|(fixture_id, attribute, value)| PresetValue {
+                    fixture_id: *fixture_id,
+                    attribute: attribute.clone(),
+                    value: *value,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn merges_known_combinations() {
+        use Attribute::{Dimmer, Pan};
+        let cases = [
+            (
+                "intensities take the highest value, however old",
+                vec![
+                    layer(0, 1, &[(1, Dimmer, 50000)]),
+                    layer(0, 2, &[(1, Dimmer, 20000)]),
+                ],
+                vec![(1, Dimmer, 50000)],
+            ),
+            (
+                "everything else goes to the latest playback",
+                vec![layer(0, 2, &[(1, Pan, 100)]), layer(0, 1, &[(1, Pan, 900)])],
+                vec![(1, Pan, 100)],
+            ),
+            (
+                "each attribute is merged on its own",
+                vec![
+                    layer(0, 1, &[(1, Dimmer, 60000), (1, Pan, 100)]),
+                    layer(0, 2, &[(1, Dimmer, 10000), (1, Pan, 200), (2, Pan, 300)]),
+                ],
+                vec![(1, Dimmer, 60000), (1, Pan, 200), (2, Pan, 300)],
+            ),
+            (
+                "higher priority wins over brighter intensities",
+                vec![
+                    layer(0, 1, &[(1, Dimmer, 65535)]),
+                    layer(5, 2, &[(1, Dimmer, 1000)]),
+                    layer(0, 3, &[(1, Dimmer, 40000)]),
+                ],
+                vec![(1, Dimmer, 1000)],
+            ),
+            (
+                "higher priority wins over later activations",
+                vec![layer(5, 1, &[(1, Pan, 100)]), layer(0, 2, &[(1, Pan, 200)])],
+                vec![(1, Pan, 100)],
+            ),
+            ("nothing set, nothing merged", vec![], vec![]),
+        ];
+
+        for (case, layers, expected) in cases {
+            let expected: HashMap<(u8, Attribute), u16> = expected
+                .into_iter()
+                .map(|(fixture_id, attribute, value)| ((fixture_id, attribute), value))
+                .collect();
+            assert_eq!(merge(&layers, &[]), expected, "{}", case);
+        }
+    }
+
+    #[test]
+    fn programmer_overrides_playbacks_at_any_priority() {
+        use Attribute::{Dimmer, Pan};
+        let layers = [
+            layer(u8::MAX, 9, &[(1, Dimmer, 65535), (1, Pan, 7), (2, Pan, 8)]),
+            layer(200, 10, &[(1, Pan, 9)]),
+        ];
+        let programmer = layer(0, 0, &[(1, Dimmer, 0), (1, Pan, 5)]).values;
+
+        let expected: HashMap<(u8, Attribute), u16> =
+            [((1, Dimmer), 0), ((1, Pan), 5), ((2, Pan), 8)]
+                .into_iter()
+                .collect();
+        assert_eq!(merge(&layers, &programmer), expected);
+    }
+
+    #[test]
+    fn activations_only_go_up() {
+        let first = activation();
+        assert!(activation() > first);
+    }
+}
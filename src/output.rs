@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::render::Frame;
+
+/// State of an output as shown to the user.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum OutputHealth {
+    Stopped,
+    Connecting,
+    Running,
+    /// Running, but the last send failed.
+    Degraded {
+        error: String,
+    },
+    /// Opening or writing failed, the next attempt is in `retry_in_ms`.
+    Reconnecting {
+        error: String,
+        retry_in_ms: u64,
+    },
+}
+
+/// Whether a network output should send `frame`, `since_last` after it last
+/// sent `last`.
+///
+/// A changed frame goes out once half the frame interval has passed: the
+/// render loop ticks at about the same rate, and a tick arriving a little
+/// early must not hold its frame back until the next one. An unchanged frame
+/// is only resent when the keep-alive is due.
+pub fn frame_due(
+    last: Option<&[u8; 512]>,
+    frame: &[u8; 512],
+    since_last: Duration,
+    frame_interval: Duration,
+    keep_alive: Duration,
+) -> bool {
+    match last {
+        None => true,
+        Some(last) if last == frame => since_last >= keep_alive,
+        Some(_) => since_last >= frame_interval / 2,
+    }
+}
+
+/// A DMX transport such as Art-Net, sACN or a serial interface.
+///
+/// The output manager hands every output the frames of the universes it
+/// asks for, one call per universe, followed by `end_of_frames`.
+pub trait DmxOutput: Send {
+    /// Shown in the UI, e.g. "Art-Net 2.255.255.255:6454".
+    fn name(&self) -> String;
+
+    /// Logical universes this output sends.
+    fn universes(&self) -> Vec<u16>;
+
+    fn start(&mut self) {}
+
+    fn send_frame(&mut self, universe: u16, frame: &[u8; 512]);
+
+    /// Every frame of this pass has been handed over.
+    fn end_of_frames(&mut self) {}
+
+    fn health(&self) -> OutputHealth;
+
+    fn stop(&mut self) {}
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputStatus {
+    pub name: String,
+    pub universes: Vec<u16>,
+    pub health: OutputHealth,
+}
+
+/// Fans each rendered frame out to every configured output.
+#[derive(Default)]
+pub struct OutputManager {
+    outputs: Vec<Box<dyn DmxOutput>>,
+    /// Union of the universes of all outputs, filled by `start`.
+    universes: Vec<u16>,
+}
+
+impl OutputManager {
+    pub fn new() -> OutputManager {
+        OutputManager::default()
+    }
+
+    pub fn add(&mut self, output: Box<dyn DmxOutput>) {
+        self.outputs.push(output);
+    }
+
+    /// Every universe some output sends, in ascending order.
+    pub fn universes(&self) -> &[u16] {
+        &self.universes
+    }
+
+    pub fn start(&mut self) {
+        let mut universes: Vec<u16> = self.outputs.iter().flat_map(|o| o.universes()).collect();
+        universes.sort_unstable();
+        universes.dedup();
+        self.universes = universes;
+
+        for output in &mut self.outputs {
+            output.start();
+        }
+    }
+
+    /// Hand every universe of `frame` to the outputs that send it.
+    pub fn send(&mut self, frame: &Frame) {
+        for output in &mut self.outputs {
+            let wanted = output.universes();
+            for (universe, dmx) in &frame.universes {
+                if wanted.contains(universe) {
+                    output.send_frame(*universe, dmx);
+                }
+            }
+            output.end_of_frames();
+        }
+    }
+
+    pub fn status(&self) -> Vec<OutputStatus> {
+        self.outputs
+            .iter()
+            .map(|o| OutputStatus {
+                name: o.name(),
+                universes: o.universes(),
+                health: o.health(),
+            })
+            .collect()
+    }
+
+    pub fn stop(&mut self) {
+        for output in &mut self.outputs {
+            output.stop();
+        }
+    }
+}
+
+/// Outputs that drop or keep the frames they get, to check the pipeline in
+/// tests.
+#[cfg(test)]
+pub mod testing {
+    use std::sync::{Arc, Mutex};
+
+    use super::{DmxOutput, OutputHealth};
+
+    /// Discards every frame.
+    pub struct NullOutput {
+        universes: Vec<u16>,
+    }
+
+    impl NullOutput {
+        pub fn new(universes: Vec<u16>) -> NullOutput {
+            NullOutput { universes }
+        }
+    }
+
+    impl DmxOutput for NullOutput {
+        fn name(&self) -> String {
+            "Null".to_string()
+        }
+
+        fn universes(&self) -> Vec<u16> {
+            self.universes.clone()
+        }
+
+        fn send_frame(&mut self, _universe: u16, _frame: &[u8; 512]) {}
+
+        fn health(&self) -> OutputHealth {
+            OutputHealth::Running
+        }
+    }
+
+    /// Universe and frame pairs in the order a `RecordingOutput` received them.
+    pub type RecordedFrames = Arc<Mutex<Vec<(u16, [u8; 512])>>>;
+
+    /// Keeps every frame it receives, to check what the pipeline sends.
+    pub struct RecordingOutput {
+        universes: Vec<u16>,
+        running: bool,
+        /// Frames in the order they were sent, shared so they can be read while
+        /// the output is owned by a manager.
+        pub frames: RecordedFrames,
+        /// Number of completed passes.
+        pub passes: Arc<Mutex<usize>>,
+    }
+
+    impl RecordingOutput {
+        pub fn new(universes: Vec<u16>) -> RecordingOutput {
+            RecordingOutput {
+                universes,
+                running: false,
+                frames: Arc::new(Mutex::new(Vec::new())),
+                passes: Arc::new(Mutex::new(0)),
+            }
+        }
+    }
+
+    impl DmxOutput for RecordingOutput {
+        fn name(&self) -> String {
+            "Recording".to_string()
+        }
+
+        fn universes(&self) -> Vec<u16> {
+            self.universes.clone()
+        }
+
+        fn start(&mut self) {
+            self.running = true;
+        }
+
+        fn send_frame(&mut self, universe: u16, frame: &[u8; 512]) {
+            self.frames.lock().unwrap().push((universe, *frame));
+        }
+
+        fn end_of_frames(&mut self) {
+            *self.passes.lock().unwrap() += 1;
+        }
+
+        fn health(&self) -> OutputHealth {
+            if self.running {
+                OutputHealth::Running
+            } else {
+                OutputHealth::Stopped
+            }
+        }
+
+        fn stop(&mut self) {
+            self.running = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        testing::{NullOutput, RecordingOutput},
+        *,
+    };
+
+    #[test]
+    fn frames_go_to_outputs_that_send_the_universe() {
+        let first = RecordingOutput::new(vec![1]);
+        let second = RecordingOutput::new(vec![1, 2]);
+        let (first_frames, second_frames) = (first.frames.clone(), second.frames.clone());
+
+        let mut manager = OutputManager::new();
+        manager.add(Box::new(first));
+        manager.add(Box::new(second));
+        manager.start();
+        assert_eq!(manager.universes(), [1, 2]);
+
+        manager.send(&Frame {
+            tick: 1,
+            universes: [(1, [1; 512]), (2, [2; 512])].into(),
+        });
+
+        assert_eq!(*first_frames.lock().unwrap(), vec![(1, [1; 512])]);
+        assert_eq!(
+            *second_frames.lock().unwrap(),
+            vec![(1, [1; 512]), (2, [2; 512])]
+        );
+    }
+
+    #[test]
+    fn health_follows_start_and_stop() {
+        let mut manager = OutputManager::new();
+        manager.add(Box::new(RecordingOutput::new(vec![1])));
+        manager.add(Box::new(NullOutput::new(vec![1])));
+        assert_eq!(manager.status()[0].health, OutputHealth::Stopped);
+
+        manager.start();
+        assert_eq!(manager.status()[0].health, OutputHealth::Running);
+        assert_eq!(manager.status()[1].health, OutputHealth::Running);
+
+        manager.stop();
+        assert_eq!(manager.status()[0].health, OutputHealth::Stopped);
+    }
+}
@@ -1,80 +1,105 @@
 use crate::lib::{
-    fixture::{Color, ColorWheel, CustomValue, Dimmer, Fixture, FixtureComponent, Focus, GoboWheel, Position},
-    universe::Universe,
+    fixture::{ColorWheel, CustomValue, Dimmer, FixtureComponent, Focus, GoboWheel, Position},
+    fixture_type::{FixtureLibrary, FixtureMode, FixtureType},
+    show::Show,
 };
 
-pub fn get_universe() -> Universe {
-    let mut universe = Universe::new();
-
-    // let mut test_light = Fixture::new(1, 17, "Test Light".to_string());
-    // test_light.add_component(FixtureComponent::Dimmer(Dimmer { intensity: 255 }));
-
-    // universe.add_fixture(test_light);
-
-    //Add 7 LED PAR fixtures, each using 6 channels
-    for i in 0..7 {
-        let dmx_start = 1 + (i * 6); // Starting from DMX address 1, each using 6 channels
-        let mut par = Fixture::new(i + 1 as u8, dmx_start as u16, format!("LED PAR {}", i + 1));
-
-        // Channel 1: Cool White
-        par.add_component(FixtureComponent::CustomValue(CustomValue {
-            name: "cool_white".to_string(),
-            value: 0,
-        }));
-
-        // Channel 2: Warm White
-        par.add_component(FixtureComponent::CustomValue(CustomValue {
-            name: "warm_white".to_string(),
-            value: 0,
-        }));
-
-        // Channel 3: Amber
-        par.add_component(FixtureComponent::CustomValue(CustomValue {
-            name: "amber".to_string(),
-            value: 0,
-        }));
-
-        // Channel 4: Color Temperature Macros
-        par.add_component(FixtureComponent::CustomValue(CustomValue {
-            name: "color_temp".to_string(),
-            value: 0, // 0-18 is OFF by default
-        }));
-
-        // Channel 5: Strobe
-        par.add_component(FixtureComponent::CustomValue(CustomValue {
-            name: "strobe".to_string(),
-            value: 0,
-        }));
-
-        // Channel 6: Master Dimmer
-        par.add_component(FixtureComponent::Dimmer(Dimmer {
-            intensity: 255, // Full intensity by default
-            local: 255,
-        }));
+/// Fixture types that are always available, also to show files.
+pub fn demo_library() -> FixtureLibrary {
+    let mut library = FixtureLibrary::new();
+
+    library.add_type(FixtureType {
+        manufacturer: "Generic".to_string(),
+        model: "LED PAR".to_string(),
+        modes: vec![FixtureMode {
+            name: "6ch".to_string(),
+            channels: vec![
+                // Channel 1: Cool White
+                FixtureComponent::CustomValue(CustomValue {
+                    name: "cool_white".to_string(),
+                    value: 0,
+                }),
+                // Channel 2: Warm White
+                FixtureComponent::CustomValue(CustomValue {
+                    name: "warm_white".to_string(),
+                    value: 0,
+                }),
+                // Channel 3: Amber
+                FixtureComponent::CustomValue(CustomValue {
+                    name: "amber".to_string(),
+                    value: 0,
+                }),
+                // Channel 4: Color Temperature Macros
+                FixtureComponent::CustomValue(CustomValue {
+                    name: "color_temp".to_string(),
+                    value: 0, // 0-18 is OFF by default
+                }),
+                // Channel 5: Strobe
+                FixtureComponent::CustomValue(CustomValue {
+                    name: "strobe".to_string(),
+                    value: 0,
+                }),
+                // Channel 6: Master Dimmer
+                FixtureComponent::Dimmer(Dimmer {
+                    intensity: 255, // Full intensity by default
+                    local: 255,
+                }),
+            ],
+        }],
+    });
+
+    library.add_type(FixtureType {
+        manufacturer: "Generic".to_string(),
+        model: "Moving Head".to_string(),
+        modes: vec![FixtureMode {
+            name: "16ch".to_string(),
+            channels: vec![
+                FixtureComponent::Position(Position::default()), // 1-4
+                FixtureComponent::ColorWheel(ColorWheel::default()), // 5
+                FixtureComponent::Gobo(GoboWheel::default()),    // 6
+                FixtureComponent::Zero,                          // 7
+                FixtureComponent::Zero,                          // 8
+                FixtureComponent::Zero,                          // 9
+                FixtureComponent::Zero,                          // 10
+                FixtureComponent::Focus(Focus { value: 0 }),     // 11
+                FixtureComponent::Max,                           // 12
+                FixtureComponent::Dimmer(Dimmer {
+                    intensity: 255,
+                    local: 255,
+                }), // 13
+                FixtureComponent::Zero,                          // 14
+                FixtureComponent::Zero,                          // 15
+                FixtureComponent::Zero,                          // 16
+            ],
+        }],
+    });
+
+    library
+}
 
-        universe.add_fixture(par);
+/// Built-in demo patch, used when no show file is given on the command line.
+pub fn get_show() -> Show {
+    let library = demo_library();
+    let mut show = Show::new();
+
+    // 7 x Generic/LED PAR/6ch at 1
+    for par in library
+        .instantiate_many("Generic/LED PAR", "6ch", 7, 1, 1, 1)
+        .expect("demo library has LED PAR 6ch")
+    {
+        show.add_fixture(par).expect("demo patch is valid");
     }
 
-    for i in 1..=2 {
-        let mut moving_head = Fixture::new(7 + i as u8, 100 + (i - 1) * 16, format!("Moving Head {}", i));
-        moving_head.add_component(FixtureComponent::Position(Position { pan: 0, tilt: 0 }));
-        moving_head.add_component(FixtureComponent::ColorWheel(ColorWheel { index: 0 })); //5
-        moving_head.add_component(FixtureComponent::Gobo(GoboWheel { index: 0 })); //6
-        moving_head.add_component(FixtureComponent::Zero); //7
-        moving_head.add_component(FixtureComponent::Zero); //8
-        moving_head.add_component(FixtureComponent::Zero); // 9
-        moving_head.add_component(FixtureComponent::Zero); // 10
-        moving_head.add_component(FixtureComponent::Focus(Focus { value: 0 })); //11
-        moving_head.add_component(FixtureComponent::Max); //12
-        moving_head.add_component(FixtureComponent::Dimmer(Dimmer { intensity: 255, local: 255 })); //13
-        moving_head.add_component(FixtureComponent::Zero); // 14
-        moving_head.add_component(FixtureComponent::Zero); // 15
-        moving_head.add_component(FixtureComponent::Zero); // 16
-
-
-        universe.add_fixture(moving_head);
+    // 2 x Generic/Moving Head/16ch at 100
+    for moving_head in library
+        .instantiate_many("Generic/Moving Head", "16ch", 2, 8, 1, 100)
+        .expect("demo library has Moving Head 16ch")
+    {
+        show.add_fixture(moving_head).expect("demo patch is valid");
     }
 
+    show.groups.insert("pars".to_string(), (1..=7).collect());
+    show.groups.insert("moving_heads".to_string(), vec![8, 9]);
 
-    return universe;
+    show
 }
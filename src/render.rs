@@ -0,0 +1,358 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::Clock,
+    effect::current_values,
+    lib::{fixture::FixtureComponent, show::Show},
+    merge::{Layer, merge},
+    output::OutputManager,
+};
+
+/// DMX of every rendered universe from one tick of the render loop. Never
+/// changed once published.
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    /// Counts up from 1 with every rendered frame.
+    pub tick: u64,
+    pub universes: BTreeMap<u16, [u8; 512]>,
+}
+
+impl Frame {
+    /// DMX of `universe`, all zero if it was not rendered.
+    pub fn universe(&self, universe: u16) -> [u8; 512] {
+        self.universes.get(&universe).copied().unwrap_or([0; 512])
+    }
+}
+
+/// The latest published frame. Readers get it without touching the show.
+#[derive(Debug, Clone, Default)]
+pub struct FrameBuffer {
+    latest: Arc<RwLock<Arc<Frame>>>,
+}
+
+impl FrameBuffer {
+    pub fn new() -> FrameBuffer {
+        FrameBuffer::default()
+    }
+
+    pub fn publish(&self, frame: Frame) {
+        *self.latest.write().unwrap() = Arc::new(frame);
+    }
+
+    pub fn latest(&self) -> Arc<Frame> {
+        self.latest.read().unwrap().clone()
+    }
+}
+
+/// Advance the cue list, the effects and the executors by `delta`, merge them
+/// with the programmer, apply the global dimmer and render `universes` merged
+/// with the external input still alive at `now`.
+pub fn render(
+    show: &mut Show,
+    delta: Duration,
+    universes: &[u16],
+    tick: u64,
+    now: Instant,
+) -> Frame {
+    let mut layers = Vec::new();
+
+    let mut cues = std::mem::take(&mut show.cues);
+    cues.tick(delta, show);
+    layers.push(Layer {
+        priority: cues.priority,
+        activated: cues.activated(),
+        values: cues.output(),
+    });
+    show.cues = cues;
+
+    // Take the effects out so we can mutably borrow the show while ticking.
+    // Each writes into the fixtures, what it set is read back as its layer.
+    let mut effects = std::mem::take(&mut show.effects);
+    for running in effects.iter_mut() {
+        running.effect.tick(delta, show);
+        layers.push(Layer {
+            priority: running.effect.priority(),
+            activated: running.activated,
+            values: current_values(show, running.effect.driven(show)),
+        });
+    }
+    effects.retain(|running| !running.effect.finished());
+    show.effects = effects;
+
+    let mut executors = std::mem::take(&mut show.executors);
+    for executor in executors.values_mut() {
+        layers.extend(executor.layer(delta, show));
+    }
+    show.executors = executors;
+
+    for ((fixture_id, attribute), value) in merge(&layers, &show.programmer.values()) {
+        if let Some(fixture) = show.get_fixture_by_id_mut(fixture_id) {
+            fixture.set_attribute(&attribute, value);
+        }
+    }
+
+    // Effective intensity of every dimmer is (local * global_dimmer) / 255
+    let global = show.global_dimmer;
+    for fixture in show.fixtures_mut() {
+        for comp in fixture.components.iter_mut() {
+            if let FixtureComponent::Dimmer(d) = comp {
+                d.intensity = ((d.local as u16 * global as u16) / 255) as u8;
+            }
+        }
+    }
+
+    Frame {
+        tick,
+        universes: universes
+            .iter()
+            .map(|&universe| (universe, show.get_dmx_values(universe, now)))
+            .collect(),
+    }
+}
+
+/// Renders consecutive frames, advancing the effects by the time `clock`
+/// moved on since the previous frame.
+pub struct Renderer {
+    clock: Arc<dyn Clock>,
+    last: Duration,
+    tick: u64,
+}
+
+impl Renderer {
+    pub fn new(clock: Arc<dyn Clock>) -> Renderer {
+        let last = clock.elapsed();
+        Renderer {
+            clock,
+            last,
+            tick: 0,
+        }
+    }
+
+    pub fn step(&mut self, show: &mut Show, universes: &[u16]) -> Frame {
+        let elapsed = self.clock.elapsed();
+        let delta = elapsed.saturating_sub(self.last);
+        self.last = elapsed;
+        self.tick += 1;
+        render(show, delta, universes, self.tick, self.clock.now())
+    }
+}
+
+/// The render loop, see `launch_render_thread`.
+pub struct RenderThread {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl RenderThread {
+    /// Stop rendering and stop every output, e.g. so sACN can terminate its
+    /// streams.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if self.thread.join().is_err() {
+            eprintln!("Render thread panicked");
+        }
+    }
+}
+
+/// Render the show `frame_rate` times a second of `clock` time, publish each
+/// frame to `frames` and hand it to `outputs`.
+///
+/// The show is locked once per tick while rendering, outputs are fed after
+/// it has been released.
+pub fn launch_render_thread(
+    show: Arc<Mutex<Show>>,
+    frames: FrameBuffer,
+    outputs: Arc<Mutex<OutputManager>>,
+    clock: Arc<dyn Clock>,
+    frame_rate: f64,
+) -> RenderThread {
+    outputs.lock().unwrap().start();
+    let output_universes = outputs.lock().unwrap().universes().to_vec();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+    let interval = Duration::from_secs_f64(1.0 / frame_rate);
+    let thread = thread::spawn(move || {
+        let mut renderer = Renderer::new(clock.clone());
+        let mut next_tick = clock.elapsed();
+        while !thread_stop.load(Ordering::Relaxed) {
+            let frame = {
+                let mut show = show.lock().unwrap();
+                let mut universes = output_universes.clone();
+                universes.extend(show.universe_numbers());
+                universes.sort_unstable();
+                universes.dedup();
+                renderer.step(&mut show, &universes)
+            };
+            outputs.lock().unwrap().send(&frame);
+            frames.publish(frame);
+
+            next_tick += interval;
+            let now = clock.elapsed();
+            if next_tick > now {
+                clock.sleep_until(next_tick);
+            } else {
+                // Fell behind, don't try to catch up with a burst
+                next_tick = now;
+            }
+        }
+        outputs.lock().unwrap().stop();
+    });
+
+    RenderThread { stop, thread }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::SimulatedClock,
+        effect::{ColorSwapEffect, start_fade},
+        lib::preset::PresetValue,
+        lib::{
+            cue::{Cue, CueCommand, CueList},
+            fixture::Attribute,
+            input::InputSource,
+        },
+        output::{OutputHealth, testing::RecordingOutput},
+        patching::get_show,
+    };
+
+    #[test]
+    fn render_applies_effects_and_global_dimmer() {
+        let mut show = get_show();
+        show.insert_present(ColorSwapEffect::new(60.0, "pars".into(), false, false));
+        show.global_dimmer = 0;
+        let universes = show.universe_numbers();
+        let now = Instant::now();
+
+        let frame = render(&mut show, Duration::from_millis(20), &universes, 1, now);
+
+        // Every PAR starts on cool white at full
+        let par = show.get_fixture_by_id(1).unwrap();
+        let cool_white = par
+            .components
+            .iter()
+            .any(|c| matches!(c, FixtureComponent::CustomValue(cv) if cv.name == "cool_white" && cv.value == 255));
+        assert!(cool_white);
+        for fixture in show.fixtures() {
+            for comp in &fixture.components {
+                if let FixtureComponent::Dimmer(d) = comp {
+                    assert_eq!(d.intensity, 0);
+                }
+            }
+        }
+        assert_eq!(frame.tick, 1);
+        assert_eq!(
+            frame.universe(universes[0]),
+            show.get_dmx_values(universes[0], now)
+        );
+    }
+
+    #[test]
+    fn render_merges_cues_and_effects() {
+        let head = |fixture_id, attribute, value| PresetValue {
+            fixture_id,
+            attribute,
+            value,
+        };
+        let mut show = get_show();
+        show.cues = CueList::new(vec![Cue {
+            number: 1,
+            name: String::new(),
+            values: vec![
+                head(8, Attribute::Dimmer, 40000),
+                head(8, Attribute::Pan, 1000),
+                head(9, Attribute::Pan, 1000),
+            ],
+            fade_in_ms: 0,
+            fade_out_ms: 0,
+            delay_ms: 0,
+            follow_ms: None,
+        }]);
+        show.cues.command(CueCommand::Go);
+        render(&mut show, Duration::from_millis(20), &[], 1, Instant::now());
+
+        // A preset fade started later takes pan, the brighter cue keeps the dimmer
+        let fade = vec![
+            head(8, Attribute::Dimmer, 10000),
+            head(8, Attribute::Pan, 5000),
+        ];
+        start_fade(&mut show, fade.clone(), Duration::from_secs(10));
+        render(&mut show, Duration::from_secs(10), &[], 2, Instant::now());
+        let fixture = show.get_fixture_by_id(8).unwrap();
+        assert_eq!(
+            fixture.get_attribute(&Attribute::Dimmer),
+            Some(40000 / 257 * 257)
+        );
+        assert_eq!(fixture.get_attribute(&Attribute::Pan), Some(5000));
+        let other = show.get_fixture_by_id(9).unwrap();
+        assert_eq!(other.get_attribute(&Attribute::Pan), Some(1000));
+
+        // Unless the cue list has the higher priority
+        show.cues.priority = 10;
+        start_fade(&mut show, fade, Duration::from_secs(10));
+        render(&mut show, Duration::from_secs(1), &[], 3, Instant::now());
+        let fixture = show.get_fixture_by_id(8).unwrap();
+        assert_eq!(fixture.get_attribute(&Attribute::Pan), Some(1000));
+    }
+
+    #[test]
+    fn input_times_out_on_the_render_clock() {
+        let clock = Arc::new(SimulatedClock::new());
+        let mut renderer = Renderer::new(clock.clone());
+        let mut show = get_show();
+        let mut source = InputSource::new(
+            "guest".to_string(),
+            "Guest Desk".to_string(),
+            100,
+            [200; 512],
+            Duration::from_secs(2),
+        );
+        source.last_update = clock.now();
+        show.inputs.update(1, source);
+
+        assert_eq!(renderer.step(&mut show, &[1]).universe(1)[0], 200);
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(renderer.step(&mut show, &[1]).universe(1)[0], 0);
+    }
+
+    #[test]
+    fn render_thread_publishes_and_sends_frames() {
+        let show = Arc::new(Mutex::new(get_show()));
+        let expected = show.lock().unwrap().get_dmx_values(1, Instant::now());
+
+        let recording = RecordingOutput::new(vec![1]);
+        let (sent, passes) = (recording.frames.clone(), recording.passes.clone());
+        let outputs = Arc::new(Mutex::new(OutputManager::new()));
+        outputs.lock().unwrap().add(Box::new(recording));
+        let frames = FrameBuffer::new();
+
+        let clock = Arc::new(SimulatedClock::new());
+        let thread =
+            launch_render_thread(show, frames.clone(), outputs.clone(), clock.clone(), 50.0);
+        while *passes.lock().unwrap() < 3 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread.stop();
+
+        assert_eq!(sent.lock().unwrap()[0], (1, expected));
+        let latest = frames.latest();
+        assert!(latest.tick >= 3);
+        // Each frame moves the simulated clock on by exactly one interval
+        assert!(clock.elapsed() >= Duration::from_millis(20) * (latest.tick as u32 - 1));
+        assert_eq!(latest.universe(1), expected);
+        assert_eq!(
+            outputs.lock().unwrap().status()[0].health,
+            OutputHealth::Stopped
+        );
+    }
+}
@@ -0,0 +1,795 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    io,
+    net::{AddrParseError, IpAddr, Ipv4Addr, SocketAddrV4, UdpSocket},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lib::{input::InputSource, show::Show},
+    output::{DmxOutput, OutputHealth, frame_due},
+};
+
+pub const SACN_PORT: u16 = 5568;
+
+/// Total length of an E1.31 data packet carrying 512 slots.
+const PACKET_LENGTH: usize = 638;
+const OPTION_STREAM_TERMINATED: u8 = 0x40;
+const OPTION_PREVIEW_DATA: u8 = 0x80;
+/// E1.31 network data loss timeout.
+const SOURCE_TIMEOUT: Duration = Duration::from_millis(2500);
+
+/// Which sACN universe (1-63999) a logical universe is sent as.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SacnUniverseMapping {
+    pub universe: u16,
+    pub sacn_universe: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SacnOutputConfig {
+    /// Shown by receivers and merge tools, at most 63 bytes are sent.
+    #[serde(default = "default_source_name")]
+    pub source_name: String,
+    /// Component identifier as a UUID string. Derived from the source name
+    /// when not given, so it stays the same across restarts.
+    #[serde(default)]
+    pub cid: Option<String>,
+    /// 0-200, receivers take the highest priority source.
+    #[serde(default = "default_priority")]
+    pub priority: u8,
+    /// Unicast receivers. When empty each universe is multicast to
+    /// 239.255.<hi>.<lo>.
+    #[serde(default)]
+    pub destinations: Vec<String>,
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// Frames per second, at most the 44 the render loop runs at.
+    #[serde(default = "default_frame_rate")]
+    pub frame_rate: f64,
+    /// An unchanged universe is resent after this many milliseconds.
+    #[serde(default = "default_keep_alive_ms")]
+    pub keep_alive_ms: u64,
+    /// Universes sent by this output. When empty every universe of the show
+    /// is sent as the sACN universe with the same number.
+    #[serde(default)]
+    pub universes: Vec<SacnUniverseMapping>,
+}
+
+fn default_source_name() -> String {
+    "illumix lite".to_string()
+}
+
+fn default_priority() -> u8 {
+    100
+}
+
+fn default_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_frame_rate() -> f64 {
+    44.0
+}
+
+fn default_keep_alive_ms() -> u64 {
+    1000
+}
+
+impl Default for SacnOutputConfig {
+    fn default() -> Self {
+        SacnOutputConfig {
+            source_name: default_source_name(),
+            cid: None,
+            priority: default_priority(),
+            destinations: Vec::new(),
+            bind: default_bind(),
+            frame_rate: default_frame_rate(),
+            keep_alive_ms: default_keep_alive_ms(),
+            universes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SacnError {
+    InvalidDestination(String, AddrParseError),
+    InvalidBind(String, AddrParseError),
+    InvalidCid(String),
+    InvalidPriority(u8),
+    InvalidUniverse { universe: u16, sacn_universe: u16 },
+    InvalidFrameRate(f64),
+    Bind(String, io::Error),
+}
+
+impl fmt::Display for SacnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SacnError::InvalidDestination(address, e) => {
+                write!(f, "invalid sACN destination '{}': {}", address, e)
+            }
+            SacnError::InvalidBind(address, e) => {
+                write!(f, "invalid sACN bind address '{}': {}", address, e)
+            }
+            SacnError::InvalidCid(cid) => write!(f, "invalid sACN CID '{}', expected a UUID", cid),
+            SacnError::InvalidPriority(priority) => {
+                write!(f, "invalid sACN priority {}, must be 0-200", priority)
+            }
+            SacnError::InvalidUniverse {
+                universe,
+                sacn_universe,
+            } => write!(
+                f,
+                "universe {} is mapped to sACN universe {}, which is not within 1-63999",
+                universe, sacn_universe
+            ),
+            SacnError::InvalidFrameRate(rate) => {
+                write!(f, "invalid sACN frame rate {}, must be above 0", rate)
+            }
+            SacnError::Bind(address, e) => {
+                write!(f, "could not bind sACN socket to {}: {}", address, e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SacnError {}
+
+/// Parse "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx".
+fn parse_cid(text: &str) -> Option<[u8; 16]> {
+    let hex: Vec<u8> = text.bytes().filter(|&b| b != b'-').collect();
+    if hex.len() != 32 || text.len() != 36 {
+        return None;
+    }
+    let mut cid = [0; 16];
+    for (byte, pair) in cid.iter_mut().zip(hex.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(cid)
+}
+
+/// A stable CID for sources that don't configure one.
+fn cid_from_name(name: &str) -> [u8; 16] {
+    let mut cid = [0; 16];
+    for (i, chunk) in cid.chunks_mut(8).enumerate() {
+        let mut hasher = DefaultHasher::new();
+        (i, name).hash(&mut hasher);
+        chunk.copy_from_slice(&hasher.finish().to_be_bytes());
+    }
+    // Mark as a version 4 variant 1 UUID
+    cid[6] = cid[6] & 0x0f | 0x40;
+    cid[8] = cid[8] & 0x3f | 0x80;
+    cid
+}
+
+/// Multicast group of an sACN universe.
+pub fn multicast_address(sacn_universe: u16) -> Ipv4Addr {
+    let [hi, lo] = sacn_universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, hi, lo)
+}
+
+/// Build an E1.31 data packet for one universe.
+fn data_packet(
+    cid: &[u8; 16],
+    source_name: &str,
+    priority: u8,
+    sequence: u8,
+    options: u8,
+    sacn_universe: u16,
+    frame: &[u8; 512],
+) -> [u8; PACKET_LENGTH] {
+    fn flags_and_length(offset: usize) -> [u8; 2] {
+        (0x7000 | (PACKET_LENGTH - offset) as u16).to_be_bytes()
+    }
+
+    let mut packet = [0; PACKET_LENGTH];
+
+    // Root layer
+    packet[0..2].copy_from_slice(&0x0010u16.to_be_bytes());
+    packet[4..16].copy_from_slice(b"ASC-E1.17\0\0\0");
+    packet[16..18].copy_from_slice(&flags_and_length(16));
+    packet[18..22].copy_from_slice(&4u32.to_be_bytes());
+    packet[22..38].copy_from_slice(cid);
+
+    // Framing layer
+    packet[38..40].copy_from_slice(&flags_and_length(38));
+    packet[40..44].copy_from_slice(&2u32.to_be_bytes());
+    for (dst, src) in packet[44..107].iter_mut().zip(source_name.bytes()) {
+        *dst = src;
+    }
+    packet[108] = priority;
+    packet[111] = sequence;
+    packet[112] = options;
+    packet[113..115].copy_from_slice(&sacn_universe.to_be_bytes());
+
+    // DMP layer
+    packet[115..117].copy_from_slice(&flags_and_length(115));
+    packet[117] = 0x02;
+    packet[118] = 0xa1;
+    packet[121..123].copy_from_slice(&1u16.to_be_bytes());
+    packet[123..125].copy_from_slice(&513u16.to_be_bytes());
+    // Start code 0 at 125, then the slots
+    packet[126..].copy_from_slice(frame);
+
+    packet
+}
+
+struct UniverseState {
+    universe: u16,
+    sacn_universe: u16,
+    /// Where this universe is sent, the multicast group or the unicast hosts.
+    destinations: Vec<SocketAddrV4>,
+    last_frame: Option<[u8; 512]>,
+    last_sent: Instant,
+    sequence: u8,
+}
+
+/// An sACN source, see `SacnOutputConfig`.
+pub struct SacnOutput {
+    socket: UdpSocket,
+    cid: [u8; 16],
+    source_name: String,
+    priority: u8,
+    frame_interval: Duration,
+    keep_alive: Duration,
+    universes: Vec<UniverseState>,
+    health: OutputHealth,
+}
+
+impl SacnOutputConfig {
+    fn mappings(&self, show: &Show) -> Vec<SacnUniverseMapping> {
+        if !self.universes.is_empty() {
+            return self.universes.clone();
+        }
+        show.universe_numbers()
+            .into_iter()
+            .map(|universe| SacnUniverseMapping {
+                universe,
+                sacn_universe: universe,
+            })
+            .collect()
+    }
+
+    pub fn cid(&self) -> Result<[u8; 16], SacnError> {
+        match &self.cid {
+            Some(text) => parse_cid(text).ok_or_else(|| SacnError::InvalidCid(text.clone())),
+            None => Ok(cid_from_name(&self.source_name)),
+        }
+    }
+
+    /// Check the settings and open the socket. The output sends the show's
+    /// universes once it is added to an output manager.
+    pub fn open(&self, show: &Show) -> Result<SacnOutput, SacnError> {
+        let cid = self.cid()?;
+        if self.priority > 200 {
+            return Err(SacnError::InvalidPriority(self.priority));
+        }
+        if !(self.frame_rate.is_finite() && self.frame_rate > 0.0) {
+            return Err(SacnError::InvalidFrameRate(self.frame_rate));
+        }
+        let unicast = self
+            .destinations
+            .iter()
+            .map(|d| {
+                d.parse::<Ipv4Addr>()
+                    .map(|ip| SocketAddrV4::new(ip, SACN_PORT))
+                    .map_err(|e| SacnError::InvalidDestination(d.clone(), e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let bind: Ipv4Addr = self
+            .bind
+            .parse()
+            .map_err(|e| SacnError::InvalidBind(self.bind.clone(), e))?;
+
+        let universes = self
+            .mappings(show)
+            .into_iter()
+            .map(|m| {
+                if !(1..=63999).contains(&m.sacn_universe) {
+                    return Err(SacnError::InvalidUniverse {
+                        universe: m.universe,
+                        sacn_universe: m.sacn_universe,
+                    });
+                }
+                let destinations = if unicast.is_empty() {
+                    vec![SocketAddrV4::new(
+                        multicast_address(m.sacn_universe),
+                        SACN_PORT,
+                    )]
+                } else {
+                    unicast.clone()
+                };
+                Ok(UniverseState {
+                    universe: m.universe,
+                    sacn_universe: m.sacn_universe,
+                    destinations,
+                    last_frame: None,
+                    last_sent: Instant::now(),
+                    sequence: 0,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let socket =
+            UdpSocket::bind((bind, 0)).map_err(|e| SacnError::Bind(self.bind.clone(), e))?;
+
+        if self.destinations.is_empty() {
+            println!("Created Socket for sACN, multicasting");
+        } else {
+            println!(
+                "Created Socket for sACN, sending to {}",
+                self.destinations.join(", ")
+            );
+        }
+
+        Ok(SacnOutput {
+            socket,
+            cid,
+            source_name: self.source_name.clone(),
+            priority: self.priority,
+            frame_interval: Duration::from_secs_f64(1.0 / self.frame_rate),
+            keep_alive: Duration::from_millis(self.keep_alive_ms),
+            universes,
+            health: OutputHealth::Stopped,
+        })
+    }
+}
+
+impl SacnOutput {
+    fn send_universe(&mut self, index: usize, frame: &[u8; 512], options: u8) {
+        let state = &mut self.universes[index];
+        state.sequence = state.sequence.wrapping_add(1);
+        let packet = data_packet(
+            &self.cid,
+            &self.source_name,
+            self.priority,
+            state.sequence,
+            options,
+            state.sacn_universe,
+            frame,
+        );
+        let mut error = None;
+        for destination in &state.destinations {
+            if let Err(e) = self.socket.send_to(&packet, destination) {
+                error = Some(format!("failed to send to {}: {}", destination, e));
+            }
+        }
+        match error {
+            Some(error) => {
+                if self.health
+                    != (OutputHealth::Degraded {
+                        error: error.clone(),
+                    })
+                {
+                    eprintln!("sACN: {}", error);
+                }
+                self.health = OutputHealth::Degraded { error };
+            }
+            None if matches!(self.health, OutputHealth::Degraded { .. }) => {
+                self.health = OutputHealth::Running;
+            }
+            None => {}
+        }
+    }
+}
+
+impl DmxOutput for SacnOutput {
+    fn name(&self) -> String {
+        format!("sACN {}", self.source_name)
+    }
+
+    fn universes(&self) -> Vec<u16> {
+        self.universes.iter().map(|state| state.universe).collect()
+    }
+
+    fn start(&mut self) {
+        self.health = OutputHealth::Running;
+    }
+
+    fn send_frame(&mut self, universe: u16, frame: &[u8; 512]) {
+        let now = Instant::now();
+        for index in 0..self.universes.len() {
+            let state = &self.universes[index];
+            if state.universe != universe {
+                continue;
+            }
+            if !frame_due(
+                state.last_frame.as_ref(),
+                frame,
+                now - state.last_sent,
+                self.frame_interval,
+                self.keep_alive,
+            ) {
+                continue;
+            }
+            self.send_universe(index, frame, 0);
+            let state = &mut self.universes[index];
+            state.last_frame = Some(*frame);
+            state.last_sent = now;
+        }
+    }
+
+    fn health(&self) -> OutputHealth {
+        self.health.clone()
+    }
+
+    /// Tell receivers the stream has ended, three times as E1.31 asks, so
+    /// they release the universes instead of waiting for the timeout.
+    fn stop(&mut self) {
+        if self.health == OutputHealth::Stopped {
+            return;
+        }
+        for _ in 0..3 {
+            for index in 0..self.universes.len() {
+                let frame = self.universes[index].last_frame.unwrap_or([0; 512]);
+                self.send_universe(index, &frame, OPTION_STREAM_TERMINATED);
+            }
+        }
+        self.health = OutputHealth::Stopped;
+    }
+}
+
+/// Receive sACN from other consoles and merge it into the show.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SacnInputConfig {
+    /// Local address of the interface to join the multicast groups on.
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// sACN universes to listen for and the logical universe each is merged into.
+    pub universes: Vec<SacnUniverseMapping>,
+    /// Priority our own output merges with.
+    #[serde(default = "default_priority")]
+    pub local_priority: u8,
+}
+
+/// A received E1.31 data packet.
+#[derive(Debug)]
+struct DataPacket {
+    cid: [u8; 16],
+    source_name: String,
+    priority: u8,
+    sequence: u8,
+    options: u8,
+    sacn_universe: u16,
+    frame: [u8; 512],
+}
+
+/// Parse an E1.31 data packet with DMX data, anything else returns `None`.
+fn parse_data_packet(packet: &[u8]) -> Option<DataPacket> {
+    if packet.len() < 126
+        || packet[0..2] != [0x00, 0x10]
+        || &packet[4..16] != b"ASC-E1.17\0\0\0"
+        || packet[18..22] != 4u32.to_be_bytes()
+        || packet[40..44] != 2u32.to_be_bytes()
+        || packet[117] != 0x02
+        || packet[125] != 0x00
+    {
+        return None;
+    }
+
+    let slots = u16::from_be_bytes([packet[123], packet[124]]).saturating_sub(1) as usize;
+    let data = &packet[126..];
+    let mut frame = [0; 512];
+    let length = slots.min(512).min(data.len());
+    frame[..length].copy_from_slice(&data[..length]);
+
+    let name = &packet[44..108];
+    let name_end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+
+    Some(DataPacket {
+        cid: packet[22..38].try_into().unwrap(),
+        source_name: String::from_utf8_lossy(&name[..name_end]).into_owned(),
+        priority: packet[108],
+        sequence: packet[111],
+        options: packet[112],
+        sacn_universe: u16::from_be_bytes([packet[113], packet[114]]),
+        frame,
+    })
+}
+
+/// Join the multicast group of every configured universe and merge what is
+/// received into `show.inputs`. Packets carrying one of `own_cids` are our
+/// own output looping back and are ignored.
+pub fn launch_sacn_receive_thread(
+    show: Arc<Mutex<Show>>,
+    config: SacnInputConfig,
+    own_cids: Vec<[u8; 16]>,
+) -> Result<(), SacnError> {
+    let bind: Ipv4Addr = config
+        .bind
+        .parse()
+        .map_err(|e| SacnError::InvalidBind(config.bind.clone(), e))?;
+    if config.local_priority > 200 {
+        return Err(SacnError::InvalidPriority(config.local_priority));
+    }
+    for m in &config.universes {
+        if !(1..=63999).contains(&m.sacn_universe) {
+            return Err(SacnError::InvalidUniverse {
+                universe: m.universe,
+                sacn_universe: m.sacn_universe,
+            });
+        }
+    }
+
+    let address = format!("0.0.0.0:{}", SACN_PORT);
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, SACN_PORT))
+        .map_err(|e| SacnError::Bind(address.clone(), e))?;
+    for m in &config.universes {
+        socket
+            .join_multicast_v4(&multicast_address(m.sacn_universe), &bind)
+            .map_err(|e| SacnError::Bind(address.clone(), e))?;
+    }
+    show.lock().unwrap().inputs.local_priority = config.local_priority;
+    println!("Listening for sACN on {} universes", config.universes.len());
+
+    let mut receiver = SacnReceiver::new(config.universes, own_cids);
+    thread::spawn(move || {
+        let mut buffer = [0u8; 1144];
+        loop {
+            let (length, source) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) => {
+                    eprintln!("sACN receive failed: {}", e);
+                    continue;
+                }
+            };
+            if let Some(packet) = parse_data_packet(&buffer[..length]) {
+                receiver.receive(packet, source.ip(), &show);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Merges received data packets into the show's inputs.
+struct SacnReceiver {
+    universes: Vec<SacnUniverseMapping>,
+    /// Our own outputs, whose packets loop back.
+    own_cids: Vec<[u8; 16]>,
+    /// Last sequence number per source and universe.
+    sequences: Vec<(String, u16, u8)>,
+}
+
+impl SacnReceiver {
+    fn new(universes: Vec<SacnUniverseMapping>, own_cids: Vec<[u8; 16]>) -> SacnReceiver {
+        SacnReceiver {
+            universes,
+            own_cids,
+            sequences: Vec::new(),
+        }
+    }
+
+    fn receive(&mut self, packet: DataPacket, from: IpAddr, show: &Mutex<Show>) {
+        if self.own_cids.contains(&packet.cid) || packet.options & OPTION_PREVIEW_DATA != 0 {
+            return;
+        }
+        let Some(mapping) = self
+            .universes
+            .iter()
+            .find(|m| m.sacn_universe == packet.sacn_universe)
+        else {
+            return;
+        };
+        let universe = mapping.universe;
+
+        let key = format!(
+            "sacn:{}",
+            packet
+                .cid
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
+
+        // Drop packets that arrive out of order, E1.31 6.7.2
+        match self
+            .sequences
+            .iter_mut()
+            .find(|(k, u, _)| *k == key && *u == universe)
+        {
+            Some((_, _, last)) => {
+                let difference = packet.sequence.wrapping_sub(*last) as i8;
+                if difference <= 0 && difference > -20 {
+                    return;
+                }
+                *last = packet.sequence;
+            }
+            None => self
+                .sequences
+                .push((key.clone(), universe, packet.sequence)),
+        }
+
+        let mut show = show.lock().unwrap();
+        if packet.options & OPTION_STREAM_TERMINATED != 0 {
+            println!("🔌 sACN source {} terminated", packet.source_name);
+            show.inputs.remove(universe, &key);
+            self.sequences
+                .retain(|(k, u, _)| !(*k == key && *u == universe));
+            return;
+        }
+        show.inputs.update(
+            universe,
+            InputSource::new(
+                key,
+                format!("{} ({})", packet.source_name, from),
+                packet.priority,
+                packet.frame,
+                SOURCE_TIMEOUT,
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    /// An output sending universe 1 to a local listener instead of port 5568.
+    fn open_output() -> (SacnOutput, UdpSocket) {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let config = SacnOutputConfig {
+            destinations: vec!["127.0.0.1".to_string()],
+            universes: vec![SacnUniverseMapping {
+                universe: 1,
+                sacn_universe: 1,
+            }],
+            ..SacnOutputConfig::default()
+        };
+        let mut output = config.open(&Show::new()).unwrap();
+        let SocketAddr::V4(address) = listener.local_addr().unwrap() else {
+            unreachable!()
+        };
+        output.universes[0].destinations = vec![address];
+        output.start();
+        (output, listener)
+    }
+
+    const CID: [u8; 16] = [7; 16];
+
+    /// A packet from `CID` for sACN universe 10.
+    fn packet(sequence: u8, options: u8, value: u8) -> DataPacket {
+        parse_data_packet(&data_packet(
+            &CID,
+            "Guest Desk",
+            120,
+            sequence,
+            options,
+            10,
+            &[value; 512],
+        ))
+        .unwrap()
+    }
+
+    /// A receiver merging sACN universe 10 into universe 1.
+    fn receiver() -> (SacnReceiver, Mutex<Show>) {
+        let universes = vec![SacnUniverseMapping {
+            universe: 1,
+            sacn_universe: 10,
+        }];
+        (
+            SacnReceiver::new(universes, Vec::new()),
+            Mutex::new(Show::new()),
+        )
+    }
+
+    fn received(show: &Mutex<Show>) -> Option<u8> {
+        let show = show.lock().unwrap();
+        show.inputs.sources(1).first().map(|source| source.frame[0])
+    }
+
+    #[test]
+    fn data_packets_survive_a_round_trip() {
+        let mut frame = [0; 512];
+        frame[0] = 1;
+        frame[511] = 255;
+        let bytes = data_packet(
+            &CID,
+            "Guest Desk",
+            120,
+            42,
+            OPTION_STREAM_TERMINATED,
+            63999,
+            &frame,
+        );
+        assert_eq!(bytes.len(), PACKET_LENGTH);
+
+        let packet = parse_data_packet(&bytes).unwrap();
+        assert_eq!(packet.cid, CID);
+        assert_eq!(packet.source_name, "Guest Desk");
+        assert_eq!(packet.priority, 120);
+        assert_eq!(packet.sequence, 42);
+        assert_eq!(packet.options, OPTION_STREAM_TERMINATED);
+        assert_eq!(packet.sacn_universe, 63999);
+        assert_eq!(packet.frame, frame);
+
+        // Truncated, or a discovery packet (framing vector 8)
+        assert!(parse_data_packet(&bytes[..100]).is_none());
+        let mut discovery = bytes;
+        discovery[40..44].copy_from_slice(&8u32.to_be_bytes());
+        assert!(parse_data_packet(&discovery).is_none());
+    }
+
+    #[test]
+    fn out_of_order_packets_are_dropped() {
+        let (mut receiver, show) = receiver();
+        let from = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        receiver.receive(packet(10, 0, 1), from, &show);
+        assert_eq!(received(&show), Some(1));
+        for (sequence, value, expected) in [
+            // Late and repeated packets
+            (9, 2, 1),
+            (10, 3, 1),
+            (11, 4, 4),
+            (120, 5, 5),
+            (230, 6, 6),
+            // Wrapping around is in order
+            (0, 7, 7),
+            // A few behind is late, far behind means the sender restarted
+            (245, 8, 7),
+            (200, 9, 9),
+        ] {
+            receiver.receive(packet(sequence, 0, value), from, &show);
+            assert_eq!(received(&show), Some(expected), "sequence {}", sequence);
+        }
+
+        let show = show.lock().unwrap();
+        let source = &show.inputs.sources(1)[0];
+        assert_eq!(source.label, "Guest Desk (127.0.0.1)");
+        assert_eq!(source.priority, 120);
+        assert_eq!(source.timeout, SOURCE_TIMEOUT);
+    }
+
+    #[test]
+    fn stream_terminated_drops_the_source() {
+        let (mut receiver, show) = receiver();
+        let from = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        receiver.receive(packet(1, 0, 1), from, &show);
+        receiver.receive(packet(2, OPTION_STREAM_TERMINATED, 1), from, &show);
+        assert_eq!(received(&show), None);
+
+        // A restarted sender is taken again, whatever its sequence
+        receiver.receive(packet(1, 0, 9), from, &show);
+        assert_eq!(received(&show), Some(9));
+    }
+
+    #[test]
+    fn own_and_preview_packets_are_ignored() {
+        let (mut receiver, show) = receiver();
+        let from = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        receiver.receive(packet(1, OPTION_PREVIEW_DATA, 1), from, &show);
+        assert_eq!(received(&show), None);
+        receiver.own_cids.push(CID);
+        receiver.receive(packet(2, 0, 1), from, &show);
+        assert_eq!(received(&show), None);
+    }
+
+    #[test]
+    fn changed_frames_go_out_every_render_tick() {
+        let (mut output, listener) = open_output();
+        let mut buffer = [0u8; 1024];
+
+        // A render loop at the output's own 44 Hz, each tick a little early
+        let tick = output.frame_interval.mul_f64(0.9);
+        for value in 1..=3u8 {
+            output.send_frame(1, &[value; 512]);
+            let length = listener.recv(&mut buffer).unwrap();
+            assert_eq!(length, PACKET_LENGTH);
+            assert_eq!(buffer[126], value);
+            std::thread::sleep(tick);
+        }
+    }
+}
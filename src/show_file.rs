@@ -0,0 +1,418 @@
+use std::{collections::BTreeMap, fmt, fs, io, path::Path, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    artnet::{ArtNetDiscoveryConfig, ArtNetInputConfig, ArtNetOutputConfig},
+    dmx::SerialDmxConfig,
+    effect::{PositionEffect, WaveformEffect},
+    executor::{Executor, ExecutorConfig, Playback, PlaybackConfig},
+    lib::{
+        cue::{Cue, CueList},
+        fixture::Fixture,
+        fixture_type::{FixtureLibrary, FixtureType, LibraryError},
+        ofl::{self, OflError},
+        show::Show,
+        universe::PatchError,
+    },
+    sacn::{SacnInputConfig, SacnOutputConfig},
+};
+
+/// On-disk description of a show: everything that used to be hard-coded in
+/// `patching::get_universe`.
+///
+/// Fixtures can either be listed one by one with their full component list,
+/// or patched from the fixture type library (`fixture_types` plus the
+/// built-in types from `patching::demo_library`). Types can also be imported
+/// from a local Open Fixture Library checkout with `ofl_directory` and
+/// `ofl_fixtures` (`"<manufacturer-key>/<fixture-key>"`):
+///
+/// ```json
+/// {
+///   "fixture_types": [
+///     {
+///       "manufacturer": "Brand",
+///       "model": "Model",
+///       "modes": [{ "name": "14ch", "channels": [{ "type": "dimmer" }, ...] }]
+///     }
+///   ],
+///   "ofl_directory": "open-fixture-library/fixtures",
+///   "ofl_fixtures": ["cameo/flat-pro-18"],
+///   "patch": [
+///     { "fixture_type": "Brand/Model", "mode": "14ch", "count": 20, "first_id": 1, "dmx_address": 1 }
+///   ],
+///   "artnet": [
+///     {
+///       "destination": "2.255.255.255",
+///       "bind": "2.0.0.10",
+///       "sync": true,
+///       "universes": [
+///         { "universe": 1, "port_address": 0 },
+///         { "universe": 2, "port_address": { "net": 0, "subnet": 0, "universe": 1 } }
+///       ]
+///     }
+///   ],
+///   "artnet_discovery": { "poll_destination": "2.255.255.255", "poll_interval_ms": 3000 },
+///   "artnet_input": {
+///     "universes": [{ "universe": 2, "port_address": 1, "merge": "ltp" }]
+///   },
+///   "sacn": [
+///     {
+///       "source_name": "illumix lite",
+///       "cid": "6f1e2d3c-4b5a-4978-8a9b-0c1d2e3f4a5b",
+///       "priority": 100,
+///       "destinations": [],
+///       "universes": [{ "universe": 1, "sacn_universe": 1 }]
+///     }
+///   ],
+///   "sacn_input": { "universes": [{ "universe": 1, "sacn_universe": 1 }], "local_priority": 100 },
+///   "serial": [{ "device": "/dev/ttyUSB0", "universe": 1, "protocol": "enttec_pro" }],
+///   "preset_file": "my-show-presets.json",
+///   "groups": { "front": [1, 2, 3], "haze": [21] },
+///   "effects": [
+///     {
+///       "attribute": "dimmer",
+///       "target": "front",
+///       "waveform": "sine",
+///       "base": 0.2,
+///       "size": 0.8,
+///       "speed": { "bpm": 120 },
+///       "phase_spread": 360,
+///       "wings": 2,
+///       "priority": 10
+///     },
+///     { "attribute": { "custom": "haze" }, "target": [21], "waveform": "square", "speed": { "hz": 0.05 } }
+///   ],
+///   "position_effects": [
+///     {
+///       "shape": "circle",
+///       "target": "heads",
+///       "center_pan": 270,
+///       "center_tilt": 90,
+///       "size": 60,
+///       "speed": { "bpm": 30 },
+///       "phase_spread": 180
+///     }
+///   ],
+///   "cues": [
+///     {
+///       "number": 1,
+///       "name": "Preset",
+///       "values": [{ "fixture_id": 1, "attribute": "dimmer", "value": 65535 }],
+///       "fade_in_ms": 3000
+///     },
+///     {
+///       "number": 2,
+///       "values": [{ "fixture_id": 1, "attribute": "dimmer", "value": 0 }],
+///       "fade_out_ms": 5000,
+///       "delay_ms": 1000,
+///       "follow_ms": 2000
+///     }
+///   ],
+///   "cue_priority": 5,
+///   "executors": [
+///     { "number": 1, "name": "Front wash", "preset": 3 },
+///     {
+///       "number": 2,
+///       "cues": [{ "number": 1, "values": [{ "fixture_id": 21, "attribute": { "custom": "haze" }, "value": 30000 }] }],
+///       "priority": 20
+///     },
+///     {
+///       "number": 3,
+///       "effect": { "attribute": "dimmer", "target": "front", "waveform": "sine", "speed": { "hz": 1.0 } },
+///       "fader": "speed"
+///     }
+///   ],
+///   "fixtures": [
+///     {
+///       "id": 21,
+///       "name": "Hazer",
+///       "universe": 2,
+///       "dmx_address": 400,
+///       "components": [{ "type": "custom_value", "name": "haze", "value": 0 }]
+///     }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShowFile {
+    #[serde(default)]
+    pub fixture_types: Vec<FixtureType>,
+    #[serde(default)]
+    pub ofl_directory: Option<PathBuf>,
+    #[serde(default)]
+    pub ofl_fixtures: Vec<String>,
+    #[serde(default)]
+    pub patch: Vec<PatchEntry>,
+    #[serde(default)]
+    pub fixtures: Vec<Fixture>,
+    /// Art-Net outputs, one output mapping every universe when not given.
+    #[serde(default)]
+    pub artnet: Option<Vec<ArtNetOutputConfig>>,
+    #[serde(default)]
+    pub artnet_discovery: ArtNetDiscoveryConfig,
+    #[serde(default)]
+    pub artnet_input: Option<ArtNetInputConfig>,
+    /// sACN (E1.31) outputs, none when not given.
+    #[serde(default)]
+    pub sacn: Vec<SacnOutputConfig>,
+    #[serde(default)]
+    pub sacn_input: Option<SacnInputConfig>,
+    /// Serial DMX interfaces, none when not given.
+    #[serde(default)]
+    pub serial: Vec<SerialDmxConfig>,
+    /// Named lists of fixture ids that effects can target.
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<u8>>,
+    /// Waveform effects running from the start of the show.
+    #[serde(default)]
+    pub effects: Vec<WaveformEffect>,
+    /// Pan/tilt shape effects running from the start of the show.
+    #[serde(default)]
+    pub position_effects: Vec<PositionEffect>,
+    /// Where stored presets are kept, `presets.json` when not given.
+    #[serde(default)]
+    pub preset_file: Option<PathBuf>,
+    /// The cue list, in any order. Each cue only lists what it changes.
+    #[serde(default)]
+    pub cues: Vec<Cue>,
+    /// Merge priority of the cue list against the effects.
+    #[serde(default)]
+    pub cue_priority: u8,
+    /// Playbacks on faders, stopped at the start of the show.
+    #[serde(default)]
+    pub executors: Vec<ExecutorConfig>,
+}
+
+/// "`count` x `fixture_type`/`mode` at `dmx_address`".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchEntry {
+    pub fixture_type: String,
+    pub mode: String,
+    #[serde(default = "default_count")]
+    pub count: u16,
+    pub first_id: u8,
+    #[serde(default = "default_universe")]
+    pub universe: u16,
+    pub dmx_address: u16,
+}
+
+fn default_count() -> u16 {
+    1
+}
+
+fn default_universe() -> u16 {
+    1
+}
+
+#[derive(Debug)]
+pub enum ShowFileError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_json::Error),
+    Library(LibraryError),
+    Ofl(OflError),
+    MissingOflDirectory,
+    /// Every fixture that could not be patched.
+    Patch(Vec<PatchError>),
+    UnknownGroupFixture {
+        group: String,
+        fixture_id: u8,
+    },
+    DuplicateCue(u32),
+    UnknownCueFixture {
+        cue: u32,
+        fixture_id: u8,
+    },
+    DuplicateExecutor(u32),
+}
+
+impl fmt::Display for ShowFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShowFileError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ShowFileError::Parse(path, e) => {
+                write!(f, "invalid show file {}: {}", path.display(), e)
+            }
+            ShowFileError::Library(e) => write!(f, "{}", e),
+            ShowFileError::Ofl(e) => write!(f, "{}", e),
+            ShowFileError::MissingOflDirectory => {
+                write!(f, "ofl_fixtures are listed but no ofl_directory is set")
+            }
+            ShowFileError::Patch(errors) => {
+                write!(f, "invalid patch:")?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+            ShowFileError::UnknownGroupFixture { group, fixture_id } => {
+                write!(
+                    f,
+                    "group {} lists fixture {} which is not patched",
+                    group, fixture_id
+                )
+            }
+            ShowFileError::DuplicateCue(number) => write!(f, "cue {} is listed twice", number),
+            ShowFileError::UnknownCueFixture { cue, fixture_id } => {
+                write!(
+                    f,
+                    "cue {} sets fixture {} which is not patched",
+                    cue, fixture_id
+                )
+            }
+            ShowFileError::DuplicateExecutor(number) => {
+                write!(f, "executor {} is listed twice", number)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShowFileError {}
+
+impl ShowFile {
+    pub fn load(path: &Path) -> Result<ShowFile, ShowFileError> {
+        let text =
+            fs::read_to_string(path).map_err(|e| ShowFileError::Io(path.to_path_buf(), e))?;
+        serde_json::from_str(&text).map_err(|e| ShowFileError::Parse(path.to_path_buf(), e))
+    }
+
+    /// The built-in fixture types extended by the imported OFL fixtures and
+    /// the types defined in the file. OFL channels that could not be mapped
+    /// are reported on stdout.
+    pub fn library(&self) -> Result<FixtureLibrary, ShowFileError> {
+        let mut library = crate::patching::demo_library();
+
+        if !self.ofl_fixtures.is_empty() {
+            let directory = self
+                .ofl_directory
+                .as_ref()
+                .ok_or(ShowFileError::MissingOflDirectory)?;
+            for key in &self.ofl_fixtures {
+                let import = ofl::import_fixture(directory, key).map_err(ShowFileError::Ofl)?;
+                for channel in &import.unmapped {
+                    println!(
+                        "⚠️ OFL {}: {}, patched as placeholder",
+                        import.fixture_type.name(),
+                        channel
+                    );
+                }
+                library.add_type(import.fixture_type);
+            }
+        }
+
+        for fixture_type in &self.fixture_types {
+            library.add_type(fixture_type.clone());
+        }
+        Ok(library)
+    }
+
+    pub fn preset_file(&self) -> PathBuf {
+        self.preset_file
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("presets.json"))
+    }
+
+    pub fn artnet_outputs(&self) -> Vec<ArtNetOutputConfig> {
+        self.artnet
+            .clone()
+            .unwrap_or_else(|| vec![ArtNetOutputConfig::default()])
+    }
+
+    pub fn to_show(&self) -> Result<Show, ShowFileError> {
+        let library = self.library()?;
+        let mut fixtures = Vec::new();
+        for entry in &self.patch {
+            fixtures.extend(
+                library
+                    .instantiate_many(
+                        &entry.fixture_type,
+                        &entry.mode,
+                        entry.count,
+                        entry.first_id,
+                        entry.universe,
+                        entry.dmx_address,
+                    )
+                    .map_err(ShowFileError::Library)?,
+            );
+        }
+        fixtures.extend(self.fixtures.iter().cloned());
+
+        let mut show = Show::new();
+        let mut errors = Vec::new();
+        for fixture in fixtures {
+            if let Err(e) = show.add_fixture(fixture) {
+                errors.push(e);
+            }
+        }
+        if !errors.is_empty() {
+            return Err(ShowFileError::Patch(errors));
+        }
+
+        for (group, ids) in &self.groups {
+            if let Some(&fixture_id) = ids.iter().find(|&&id| show.get_fixture_by_id(id).is_none())
+            {
+                return Err(ShowFileError::UnknownGroupFixture {
+                    group: group.clone(),
+                    fixture_id,
+                });
+            }
+        }
+        show.groups = self.groups.clone();
+        for effect in &self.effects {
+            show.insert_present(effect.clone());
+        }
+        for effect in &self.position_effects {
+            show.insert_present(effect.clone());
+        }
+
+        show.cues = cue_list(&show, &self.cues)?;
+        show.cues.priority = self.cue_priority;
+
+        for config in &self.executors {
+            let playback = match &config.playback {
+                PlaybackConfig::Preset(number) => Playback::Preset(*number),
+                PlaybackConfig::Cues(cues) => Playback::CueList(cue_list(&show, cues)?),
+                PlaybackConfig::Effect(effect) => Playback::Effect(Box::new(effect.clone())),
+                PlaybackConfig::PositionEffect(effect) => {
+                    Playback::Effect(Box::new(effect.clone()))
+                }
+            };
+            if let Playback::Effect(effect) = &playback {
+                show.check_effect_target(effect.as_ref());
+            }
+            let mut executor = Executor::new(playback);
+            executor.name = config.name.clone();
+            executor.priority = config.priority;
+            executor.fader_mode = config.fader;
+            if show.executors.insert(config.number, executor).is_some() {
+                return Err(ShowFileError::DuplicateExecutor(config.number));
+            }
+        }
+        Ok(show)
+    }
+}
+
+/// `cues` sorted by number, checked for duplicates and unpatched fixtures.
+fn cue_list(show: &Show, cues: &[Cue]) -> Result<CueList, ShowFileError> {
+    let mut cues = cues.to_vec();
+    cues.sort_by_key(|cue| cue.number);
+    if let Some(pair) = cues
+        .windows(2)
+        .find(|pair| pair[0].number == pair[1].number)
+    {
+        return Err(ShowFileError::DuplicateCue(pair[0].number));
+    }
+    for cue in &cues {
+        if let Some(value) = cue
+            .values
+            .iter()
+            .find(|value| show.get_fixture_by_id(value.fixture_id).is_none())
+        {
+            return Err(ShowFileError::UnknownCueFixture {
+                cue: cue.number,
+                fixture_id: value.fixture_id,
+            });
+        }
+    }
+    Ok(CueList::new(cues))
+}